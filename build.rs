@@ -0,0 +1,13 @@
+// Regenerates the C header of the API in src/ffi.rs, so it can not drift from the code.
+fn main() {
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    let config = cbindgen::Config::from_file("cbindgen.toml").expect("invalid cbindgen.toml");
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src("src/ffi.rs")
+        .generate()
+        .expect("unable to generate the C header")
+        .write_to_file("include/monkey.h");
+}
@@ -0,0 +1,95 @@
+/* C API of the Monkey interpreter, generated from src/ffi.rs by build.rs.
+ *
+ * Every pointer returned by these functions is owned by the caller and released with
+ * the matching *_free function. Strings are UTF-8 and NUL terminated.
+ */
+
+#ifndef MONKEY_H
+#define MONKEY_H
+
+#include <stdint.h>
+
+typedef struct MonkeyInterpreter MonkeyInterpreter;
+
+/**
+ * Outcome of [`monkey_eval`]: the value of the script or the error it failed with.
+ */
+typedef struct MonkeyResult MonkeyResult;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates an interpreter whose globals persist between calls to [`monkey_eval`].
+ */
+MonkeyInterpreter *monkey_interpreter_new(void);
+
+/**
+ * # Safety
+ *
+ * `interpreter` must be null or come from [`monkey_interpreter_new`] and not have been
+ * freed already.
+ */
+void monkey_interpreter_free(MonkeyInterpreter *interpreter);
+
+/**
+ * Evaluates `source`, never returning null. Null arguments, invalid UTF-8 and
+ * panics are reported as errors of the result.
+ *
+ * # Safety
+ *
+ * `interpreter` must be null or a live pointer from [`monkey_interpreter_new`], and
+ * `source` must be null or point to a NUL terminated string.
+ */
+MonkeyResult *monkey_eval(MonkeyInterpreter *interpreter, const char *source);
+
+/**
+ * Returns 1 if the evaluation failed, 0 otherwise.
+ *
+ * # Safety
+ *
+ * `result` must be a live pointer from [`monkey_eval`].
+ */
+int monkey_result_is_error(const MonkeyResult *result);
+
+/**
+ * The value as `puts` would print it, or the error message. Release it with
+ * [`monkey_string_free`].
+ *
+ * # Safety
+ *
+ * `result` must be a live pointer from [`monkey_eval`].
+ */
+char *monkey_result_to_string(const MonkeyResult *result);
+
+/**
+ * Stores the value in `out` and returns 1 if it is an integer, returns 0 otherwise.
+ *
+ * # Safety
+ *
+ * `result` must be a live pointer from [`monkey_eval`] and `out` must be null or
+ * valid for writes.
+ */
+int monkey_result_to_int(const MonkeyResult *result, int64_t *out);
+
+/**
+ * # Safety
+ *
+ * `result` must be null or come from [`monkey_eval`] and not have been freed already.
+ */
+void monkey_result_free(MonkeyResult *result);
+
+/**
+ * # Safety
+ *
+ * `text` must be null or come from [`monkey_result_to_string`] and not have been freed
+ * already.
+ */
+void monkey_string_free(char *text);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* MONKEY_H */
@@ -1,13 +1,18 @@
-use std::fmt::Display;
+use std::fmt::{Display, Formatter, Result as FmtResult};
 
 use anyhow::bail;
 
-use crate::token::{Token, TokenKind};
+use crate::{
+    diagnostic::Diagnostic,
+    parser::OperatorPrecedence,
+    token::{Comment, Span, Token, TokenKind},
+};
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct Program {
     pub statments: Vec<Statement>,
-    pub errors: Vec<String>,
+    pub errors: Vec<Diagnostic>,
+    pub comments: Vec<Comment>,
 }
 
 impl Default for Program {
@@ -21,6 +26,7 @@ impl Program {
         Program {
             statments: Vec::new(),
             errors: Vec::new(),
+            comments: Vec::new(),
         }
     }
 }
@@ -29,20 +35,39 @@ impl Program {
 pub enum Statement {
     Let(LetStatement),
     Return(ReturnStatement),
+    Throw(ThrowStatement),
     Expression(ExpressionStatement),
     Block(BlockStatement),
 }
 
+impl Statement {
+    /// Source covered by the statement, including its semicolon.
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Let(statement) => statement.span,
+            Statement::Return(statement) => statement.span,
+            Statement::Throw(statement) => statement.span,
+            Statement::Expression(statement) => statement.span,
+            Statement::Block(block) => block.span,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Expression {
     Callable(CallableExpression),
     IntegerLiteral(IntegerLiteral),
     StringLiteral(StringLiteral),
     BooleanLiteral(BooleanLiteral),
+    ArrayLiteral(ArrayLiteral),
+    HashLiteral(HashLiteral),
     Prefix(PrefixExpression),
     Infix(InfixExpression),
     If(IfExpression),
+    Try(TryExpression),
     Call(CallExpression),
+    Propagate(PropagateExpression),
+    Match(MatchExpression),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -51,57 +76,262 @@ pub enum CallableExpression {
     FunctionLiteral(FunctionLiteral),
 }
 
+impl Expression {
+    /// Source covered by the expression, without enclosing parentheses.
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Callable(callable) => callable.span(),
+            Expression::IntegerLiteral(literal) => literal.span,
+            Expression::StringLiteral(literal) => literal.span,
+            Expression::BooleanLiteral(literal) => literal.span,
+            Expression::ArrayLiteral(literal) => literal.span,
+            Expression::HashLiteral(literal) => literal.span,
+            Expression::Prefix(exp) => exp.span,
+            Expression::Infix(exp) => exp.span,
+            Expression::If(exp) => exp.span,
+            Expression::Try(exp) => exp.span,
+            Expression::Call(call) => call.span,
+            Expression::Propagate(exp) => exp.span,
+            Expression::Match(exp) => exp.span,
+        }
+    }
+}
+
+impl CallableExpression {
+    pub fn span(&self) -> Span {
+        match self {
+            CallableExpression::Identifier(ident) => ident.span,
+            CallableExpression::FunctionLiteral(literal) => literal.span,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CallExpression {
     pub func: CallableExpression,
     pub arguments: Vec<Expression>,
+    pub span: Span,
+    /// One based line the call starts on, for tracebacks.
+    pub line: usize,
+}
+
+/// `value?`, the value inside an `ok`, or else an early return of the `err` from the
+/// enclosing function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PropagateExpression {
+    pub value: Box<Expression>,
+    pub span: Span,
+}
+
+/// `match (value) { pattern if guard => body, ... }`, the first arm whose pattern
+/// matches and whose guard holds gives the value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchExpression {
+    pub value: Box<Expression>,
+    pub arms: Vec<MatchArm>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expression>,
+    pub body: ArmBody,
+}
+
+/// `=> value`, or `=> { statements }` whose last statement gives the value. A hash literal
+/// as the value is written in parentheses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArmBody {
+    Expression(Expression),
+    Block(BlockStatement),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    /// `_`, matches anything without binding it.
+    Wildcard(Span),
+    Binding(Identifier),
+    /// An integer, string or boolean literal, matching values equal to it.
+    Literal(Expression),
+    Array(ArrayPattern),
+    Hash(HashPattern),
+    /// `pattern = default`, an element of an array or hash pattern used for the default
+    /// when the element or key is missing.
+    Default(DefaultPattern),
+}
+
+/// `[a, b, ...rest]`, matching arrays of exactly as many elements unless it has a rest or
+/// defaults.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArrayPattern {
+    pub elements: Vec<Pattern>,
+    /// Bound to an array of the elements after the others.
+    pub rest: Option<Identifier>,
+    pub span: Span,
+}
+
+/// `{name, age: years}`, matching hashes that have at least these string keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashPattern {
+    pub fields: Vec<FieldPattern>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefaultPattern {
+    pub pattern: Box<Pattern>,
+    pub default: Expression,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldPattern {
+    pub key: Identifier,
+    /// A binding of the key itself for the `{name}` shorthand.
+    pub pattern: Pattern,
+}
+
+impl Pattern {
+    pub fn span(&self) -> Span {
+        match self {
+            Pattern::Wildcard(span) => *span,
+            Pattern::Binding(ident) => ident.span,
+            Pattern::Literal(exp) => exp.span(),
+            Pattern::Array(pattern) => pattern.span,
+            Pattern::Hash(pattern) => pattern.span,
+            Pattern::Default(pattern) => pattern.span,
+        }
+    }
+
+    /// The default values in it, in source order.
+    pub fn defaults(&self) -> Vec<&Expression> {
+        match self {
+            Pattern::Wildcard(_) | Pattern::Binding(_) | Pattern::Literal(_) => Vec::new(),
+            Pattern::Array(pattern) => pattern
+                .elements
+                .iter()
+                .flat_map(Pattern::defaults)
+                .collect(),
+            Pattern::Hash(pattern) => pattern
+                .fields
+                .iter()
+                .flat_map(|field| field.pattern.defaults())
+                .collect(),
+            Pattern::Default(pattern) => {
+                let mut defaults = pattern.pattern.defaults();
+                defaults.push(&pattern.default);
+                defaults
+            }
+        }
+    }
+
+    /// The names it binds, in source order.
+    pub fn bindings(&self) -> Vec<&Identifier> {
+        match self {
+            Pattern::Wildcard(_) | Pattern::Literal(_) => Vec::new(),
+            Pattern::Binding(ident) => vec![ident],
+            Pattern::Array(pattern) => pattern
+                .elements
+                .iter()
+                .flat_map(Pattern::bindings)
+                .chain(pattern.rest.iter().filter(|rest| rest.value != "_"))
+                .collect(),
+            Pattern::Hash(pattern) => pattern
+                .fields
+                .iter()
+                .flat_map(|field| field.pattern.bindings())
+                .collect(),
+            Pattern::Default(pattern) => pattern.pattern.bindings(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LetStatement {
-    pub name: Identifier,
+    pub pattern: Pattern,
     pub value: Expression,
+    pub span: Span,
+}
+
+impl LetStatement {
+    /// The name bound, unless it destructures the value.
+    pub fn name(&self) -> Option<&Identifier> {
+        match &self.pattern {
+            Pattern::Binding(ident) => Some(ident),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ReturnStatement {
     pub value: Expression,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThrowStatement {
+    pub value: Expression,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ExpressionStatement {
     pub expression: Expression,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BlockStatement {
     pub statements: Vec<Statement>,
+    /// From the opening to the closing brace.
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Identifier {
     pub value: String,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct IntegerLiteral {
     pub value: i64,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct StringLiteral {
     pub value: String,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BooleanLiteral {
     pub value: bool,
+    pub span: Span,
+}
+
+/// `[1, 2, 3]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArrayLiteral {
+    pub elements: Vec<Expression>,
+    pub span: Span,
+}
+
+/// `{"name": "ada", 1: true}`, keys are expressions giving integers, booleans or strings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashLiteral {
+    pub pairs: Vec<(Expression, Expression)>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FunctionLiteral {
     pub body: BlockStatement,
     pub parameters: Vec<Identifier>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -110,6 +340,7 @@ pub struct PrefixExpression {
     pub right: Box<Expression>,
     // Only allow prefix operators bang, minus
     pub operator: Operator,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -118,6 +349,7 @@ pub struct InfixExpression {
     pub left: Box<Expression>,
     pub right: Box<Expression>,
     pub operator: Operator,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -125,6 +357,23 @@ pub struct IfExpression {
     pub condition: Box<Expression>,
     pub consequence: BlockStatement,
     pub alternative: Option<BlockStatement>,
+    pub span: Span,
+}
+
+/// `try { } catch (e) { } finally { }`, with a `catch`, a `finally` or both.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TryExpression {
+    pub body: BlockStatement,
+    pub catch: Option<CatchClause>,
+    pub finally: Option<BlockStatement>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatchClause {
+    /// Bound to the error in the body.
+    pub name: Identifier,
+    pub body: BlockStatement,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -139,6 +388,7 @@ pub enum Operator {
     Lt,
     Gt,
     Lparen,
+    Question,
 }
 
 impl Display for Operator {
@@ -149,11 +399,12 @@ impl Display for Operator {
             Operator::Bang => write!(f, "!"),
             Operator::Asterisk => write!(f, "*"),
             Operator::Slash => write!(f, "/"),
-            Operator::Eq => write!(f, "="),
+            Operator::Eq => write!(f, "=="),
             Operator::NotEq => write!(f, "!="),
             Operator::Lt => write!(f, "<"),
             Operator::Gt => write!(f, ">"),
             Operator::Lparen => write!(f, "("),
+            Operator::Question => write!(f, "?"),
         }
     }
 }
@@ -173,14 +424,395 @@ impl TryFrom<&Token<'_>> for Operator {
             TokenKind::Eq => Ok(Operator::Eq),
             TokenKind::NotEq => Ok(Operator::NotEq),
             TokenKind::Lparen => Ok(Operator::Lparen),
+            TokenKind::Question => Ok(Operator::Question),
             _ => bail!("Token cannot be converted into operator"),
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub enum Node {
-    Program(Program),
-    Statement(Statement),
-    Expression(Expression),
+// Nodes print back as Monkey source that parses to the same tree, with parentheses only
+// where precedence requires them.
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        fmt_statements(f, &self.statments, "\n")
+    }
+}
+
+/// Expression statements have no semicolon of their own, so one is added where the next
+/// statement could otherwise continue the expression.
+fn fmt_statements(f: &mut Formatter<'_>, statements: &[Statement], separator: &str) -> FmtResult {
+    for (idx, statement) in statements.iter().enumerate() {
+        if idx > 0 {
+            write!(f, "{}", separator)?;
+        }
+        write!(f, "{}", statement)?;
+        if matches!(statement, Statement::Expression(_)) && idx + 1 < statements.len() {
+            write!(f, ";")?;
+        }
+    }
+    Ok(())
+}
+
+fn fmt_list<T: Display>(f: &mut Formatter<'_>, items: &[T]) -> FmtResult {
+    for (idx, item) in items.iter().enumerate() {
+        if idx > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+impl Display for Statement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Statement::Let(statement) => write!(f, "{}", statement),
+            Statement::Return(statement) => write!(f, "{}", statement),
+            Statement::Throw(statement) => write!(f, "{}", statement),
+            Statement::Expression(statement) => write!(f, "{}", statement),
+            Statement::Block(block) => write!(f, "{}", block),
+        }
+    }
+}
+
+impl Display for LetStatement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "let {} = {};", self.pattern, self.value)
+    }
+}
+
+impl Display for ReturnStatement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "return {};", self.value)
+    }
+}
+
+impl Display for ThrowStatement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "throw {};", self.value)
+    }
+}
+
+impl Display for ExpressionStatement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.expression)
+    }
+}
+
+impl Display for BlockStatement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        if self.statements.is_empty() {
+            return write!(f, "{{ }}");
+        }
+        write!(f, "{{ ")?;
+        fmt_statements(f, &self.statements, " ")?;
+        write!(f, " }}")
+    }
+}
+
+impl Expression {
+    pub(crate) fn precedence(&self) -> OperatorPrecedence {
+        match self {
+            Expression::Infix(exp) => (&exp.operator).into(),
+            Expression::Prefix(_) => OperatorPrecedence::Prefix,
+            // they extend as far right as they can, so are always wrapped as operands
+            Expression::If(_)
+            | Expression::Try(_)
+            | Expression::Match(_)
+            | Expression::Callable(CallableExpression::FunctionLiteral(_)) => {
+                OperatorPrecedence::Lowest
+            }
+            _ => OperatorPrecedence::Call,
+        }
+    }
+}
+
+struct Operand<'a>(&'a Expression, bool);
+
+impl Display for Operand<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Operand(exp, true) => write!(f, "({})", exp),
+            Operand(exp, false) => write!(f, "{}", exp),
+        }
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Expression::Callable(callable) => write!(f, "{}", callable),
+            Expression::IntegerLiteral(literal) => write!(f, "{}", literal),
+            Expression::StringLiteral(literal) => write!(f, "{}", literal),
+            Expression::BooleanLiteral(literal) => write!(f, "{}", literal),
+            Expression::ArrayLiteral(literal) => write!(f, "{}", literal),
+            Expression::HashLiteral(literal) => write!(f, "{}", literal),
+            Expression::Prefix(exp) => write!(f, "{}", exp),
+            Expression::Infix(exp) => write!(f, "{}", exp),
+            Expression::If(exp) => write!(f, "{}", exp),
+            Expression::Try(exp) => write!(f, "{}", exp),
+            Expression::Call(exp) => write!(f, "{}", exp),
+            Expression::Propagate(exp) => write!(f, "{}", exp),
+            Expression::Match(exp) => write!(f, "{}", exp),
+        }
+    }
+}
+
+impl Display for CallableExpression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            CallableExpression::Identifier(ident) => write!(f, "{}", ident),
+            CallableExpression::FunctionLiteral(literal) => write!(f, "{}", literal),
+        }
+    }
+}
+
+impl Display for CallExpression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match &self.func {
+            CallableExpression::FunctionLiteral(literal) => write!(f, "({})", literal)?,
+            CallableExpression::Identifier(ident) => write!(f, "{}", ident)?,
+        }
+        write!(f, "(")?;
+        fmt_list(f, &self.arguments)?;
+        write!(f, ")")
+    }
+}
+
+impl Display for Identifier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.value)
+    }
+}
+
+impl Display for IntegerLiteral {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.value)
+    }
+}
+
+impl Display for StringLiteral {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "\"{}\"", self.value)
+    }
+}
+
+impl Display for BooleanLiteral {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.value)
+    }
+}
+
+impl Display for ArrayLiteral {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "[")?;
+        fmt_list(f, &self.elements)?;
+        write!(f, "]")
+    }
+}
+
+impl Display for HashLiteral {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{{")?;
+        for (idx, (key, value)) in self.pairs.iter().enumerate() {
+            if idx > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {}", key, value)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl Display for FunctionLiteral {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "fn(")?;
+        fmt_list(f, &self.parameters)?;
+        write!(f, ") {}", self.body)
+    }
+}
+
+impl Display for PrefixExpression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let wrap = self.right.precedence() < OperatorPrecedence::Prefix;
+        write!(f, "{}{}", self.operator, Operand(&self.right, wrap))
+    }
+}
+
+impl Display for InfixExpression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        // operators are left associative, so an equal right operand needs parentheses
+        let precedence: OperatorPrecedence = (&self.operator).into();
+        let left = Operand(&self.left, self.left.precedence() < precedence);
+        let right = Operand(&self.right, self.right.precedence() <= precedence);
+        write!(f, "{} {} {}", left, self.operator, right)
+    }
+}
+
+impl Display for PropagateExpression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let wrap = self.value.precedence() < OperatorPrecedence::Call;
+        write!(f, "{}?", Operand(&self.value, wrap))
+    }
+}
+
+impl Display for MatchExpression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "match ({}) {{ ", self.value)?;
+        fmt_list(f, &self.arms)?;
+        if !self.arms.is_empty() {
+            write!(f, " ")?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl Display for MatchArm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.pattern)?;
+        if let Some(guard) = &self.guard {
+            write!(f, " if {}", guard)?;
+        }
+        match &self.body {
+            // a `{` right after `=>` starts a block
+            ArmBody::Expression(exp) => {
+                let wrap = matches!(exp, Expression::HashLiteral(_));
+                write!(f, " => {}", Operand(exp, wrap))
+            }
+            ArmBody::Block(block) => write!(f, " => {}", block),
+        }
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Pattern::Wildcard(_) => write!(f, "_"),
+            Pattern::Binding(ident) => write!(f, "{}", ident),
+            Pattern::Literal(exp) => write!(f, "{}", exp),
+            Pattern::Array(pattern) => {
+                write!(f, "[")?;
+                fmt_list(f, &pattern.elements)?;
+                if let Some(rest) = &pattern.rest {
+                    if !pattern.elements.is_empty() {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "...{}", rest)?;
+                }
+                write!(f, "]")
+            }
+            Pattern::Hash(pattern) => {
+                write!(f, "{{")?;
+                fmt_list(f, &pattern.fields)?;
+                write!(f, "}}")
+            }
+            Pattern::Default(pattern) => write!(f, "{} = {}", pattern.pattern, pattern.default),
+        }
+    }
+}
+
+impl Display for FieldPattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let (pattern, default) = match &self.pattern {
+            Pattern::Default(pattern) => (&*pattern.pattern, Some(&pattern.default)),
+            pattern => (pattern, None),
+        };
+        match pattern {
+            Pattern::Binding(ident) if ident.value == self.key.value => write!(f, "{}", ident)?,
+            pattern => write!(f, "{}: {}", self.key, pattern)?,
+        }
+        if let Some(default) = default {
+            write!(f, " = {}", default)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for IfExpression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "if ({}) {}", self.condition, self.consequence)?;
+        if let Some(alternative) = &self.alternative {
+            write!(f, " else {}", alternative)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for TryExpression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "try {}", self.body)?;
+        if let Some(catch) = &self.catch {
+            write!(f, " catch ({}) {}", catch.name, catch.body)?;
+        }
+        if let Some(finally) = &self.finally {
+            write!(f, " finally {}", finally)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{lexer::Lexer, parser::Parser};
+
+    #[test]
+    fn test_display_round_trips() {
+        let tests = vec![
+            ("let x = 5;", "let x = 5;"),
+            ("return   x;", "return x;"),
+            ("2 // two\n- 1", "2 - 1"),
+            ("\"monkey\"", "\"monkey\""),
+            ("-a * b", "-a * b"),
+            ("-(a + b)", "-(a + b)"),
+            ("!!true", "!!true"),
+            ("a + b * c", "a + b * c"),
+            ("(a + b) * c", "(a + b) * c"),
+            ("a - (b - c)", "a - (b - c)"),
+            ("(a - b) - c", "a - b - c"),
+            ("a == b != (c < d)", "a == b != c < d"),
+            ("1 + 2 == 3", "1 + 2 == 3"),
+            ("add(1, 2 * 3, f(x))", "add(1, 2 * 3, f(x))"),
+            ("fn() {}", "fn() { }"),
+            (
+                "fn(x, y) { let z = x + y; z * 2 }",
+                "fn(x, y) { let z = x + y; z * 2 }",
+            ),
+            ("fn(x) { x; x }(5)", "(fn(x) { x; x })(5)"),
+            (
+                "if (x > 1) { x } else { return 1; }",
+                "if (x > 1) { x } else { return 1; }",
+            ),
+            (
+                "let max = fn(a, b) { if (a > b) { a } else { b } };",
+                "let max = fn(a, b) { if (a > b) { a } else { b } };",
+            ),
+            ("a\nb\nlet c = 1;", "a;\nb;\nlet c = 1;"),
+            ("throw   \"boom\"", "throw \"boom\";"),
+            (
+                "try { f(1) } catch (e) { throw e; } finally { puts(2) }",
+                "try { f(1) } catch (e) { throw e; } finally { puts(2) }",
+            ),
+            ("try {} finally {}", "try { } finally { }"),
+            ("-try { 1 } catch (e) { 2 }", "-(try { 1 } catch (e) { 2 })"),
+            ("f(x)? + -g()?", "f(x)? + -g()?"),
+            ("(1 + x)?", "(1 + x)?"),
+            (
+                "match (x) { {a: [b, ...c]} => b }",
+                "match (x) { {a: [b, ...c]} => b }",
+            ),
+            ("1 + match (x) { _ => 1 }", "1 + (match (x) { _ => 1 })"),
+        ];
+
+        for (input, expected) in tests {
+            let program = Parser::new(Lexer::new(input)).parse_program();
+            assert!(program.errors.is_empty(), "{}: {:?}", input, program.errors);
+            let printed = program.to_string();
+            assert_eq!(printed, expected, "{}", input);
+
+            // spans differ, so compare the trees through their printed form
+            let reparsed = Parser::new(Lexer::new(&printed)).parse_program();
+            assert_eq!(reparsed.to_string(), printed);
+        }
+    }
 }
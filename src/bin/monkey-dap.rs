@@ -0,0 +1,8 @@
+use std::io::{self, BufReader};
+
+use anyhow::Result;
+use thorsten_interpreter::dap;
+
+fn main() -> Result<()> {
+    dap::run(BufReader::new(io::stdin()), io::stdout())
+}
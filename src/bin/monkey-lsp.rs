@@ -0,0 +1,8 @@
+use std::io;
+
+use anyhow::Result;
+use thorsten_interpreter::lsp;
+
+fn main() -> Result<()> {
+    lsp::run(io::stdin().lock(), io::stdout().lock())
+}
@@ -0,0 +1,156 @@
+use std::io::{BufRead, Write};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    evaluator::RuntimeError,
+    object::{ErrorObj, Object},
+};
+
+/// Functions available to every script without being bound, a `let` with the same name
+/// shadows them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    Puts,
+    Gets,
+    ErrorMessage,
+    ErrorKind,
+    ErrorTrace,
+    Ok,
+    Err,
+    IsOk,
+    UnwrapOr,
+}
+
+impl Builtin {
+    pub const ALL: [Builtin; 9] = [
+        Builtin::Puts,
+        Builtin::Gets,
+        Builtin::ErrorMessage,
+        Builtin::ErrorKind,
+        Builtin::ErrorTrace,
+        Builtin::Ok,
+        Builtin::Err,
+        Builtin::IsOk,
+        Builtin::UnwrapOr,
+    ];
+
+    pub fn lookup(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|builtin| builtin.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Puts => "puts",
+            Builtin::Gets => "gets",
+            Builtin::ErrorMessage => "error_message",
+            Builtin::ErrorKind => "error_kind",
+            Builtin::ErrorTrace => "error_trace",
+            Builtin::Ok => "ok",
+            Builtin::Err => "err",
+            Builtin::IsOk => "is_ok",
+            Builtin::UnwrapOr => "unwrap_or",
+        }
+    }
+
+    fn check_arity(&self, args: &[Object], want: usize) -> Result<()> {
+        if args.len() != want {
+            bail!(RuntimeError::TypeError(format!(
+                "wrong number of arguments to {}: got {}, want {}",
+                self.name(),
+                args.len(),
+                want
+            )));
+        }
+        Ok(())
+    }
+
+    fn error_argument<'a>(&self, args: &'a [Object]) -> Result<&'a ErrorObj> {
+        self.check_arity(args, 1)?;
+        match &args[0] {
+            Object::Error(error) => Ok(error),
+            obj => bail!(RuntimeError::TypeError(format!(
+                "argument to {} must be ERROR, got {}",
+                self.name(),
+                obj.type_val()
+            ))),
+        }
+    }
+
+    fn result_argument(&self, args: &[Object], want: usize) -> Result<Result<Object, Object>> {
+        self.check_arity(args, want)?;
+        match &args[0] {
+            Object::Ok(value) => Ok(Ok(*value.clone())),
+            Object::Err(error) => Ok(Err(*error.clone())),
+            obj => bail!(RuntimeError::TypeError(format!(
+                "argument to {} must be OK or ERR, got {}",
+                self.name(),
+                obj.type_val()
+            ))),
+        }
+    }
+
+    pub fn apply(
+        &self,
+        args: &[Object],
+        output: &mut dyn Write,
+        input: &mut dyn BufRead,
+    ) -> Result<Object> {
+        match self {
+            Builtin::Puts => {
+                for arg in args {
+                    writeln!(output, "{}", arg).context("unable to write script output")?;
+                }
+                Ok(Object::Null)
+            }
+            Builtin::Gets => {
+                self.check_arity(args, 0)?;
+
+                let mut line = String::new();
+                let read = input
+                    .read_line(&mut line)
+                    .context("unable to read script input")?;
+                if read == 0 {
+                    return Ok(Object::Null);
+                }
+                let len = line.trim_end_matches(['\n', '\r']).len();
+                line.truncate(len);
+                Ok(Object::String(line))
+            }
+            Builtin::ErrorMessage => {
+                let error = self.error_argument(args)?;
+                Ok(Object::String(error.message.clone()))
+            }
+            Builtin::ErrorKind => {
+                let error = self.error_argument(args)?;
+                Ok(Object::String(error.kind.clone()))
+            }
+            // the calls, innermost first, as tracebacks list them
+            Builtin::ErrorTrace => {
+                let error = self.error_argument(args)?;
+                let frames = error.trace.iter().rev();
+                Ok(Object::Array(
+                    frames
+                        .map(|frame| Object::String(frame.to_string()))
+                        .collect(),
+                ))
+            }
+            Builtin::Ok => {
+                self.check_arity(args, 1)?;
+                Ok(Object::Ok(Box::new(args[0].clone())))
+            }
+            Builtin::Err => {
+                self.check_arity(args, 1)?;
+                Ok(Object::Err(Box::new(args[0].clone())))
+            }
+            Builtin::IsOk => {
+                let result = self.result_argument(args, 1)?;
+                Ok(Object::Boolean(result.is_ok()))
+            }
+            Builtin::UnwrapOr => {
+                let result = self.result_argument(args, 2)?;
+                Ok(result.unwrap_or_else(|_| args[1].clone()))
+            }
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    hash::Hash,
+};
+
+use crate::object::{HashKey, Object};
+
+/// Why a Monkey value could not be converted into the requested Rust type. Types are
+/// named like [`Object::type_val`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConversionError {
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    OutOfRange {
+        value: i64,
+        target: &'static str,
+    },
+}
+
+impl Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConversionError::TypeMismatch { expected, found } => {
+                write!(f, "type mismatch: expected {}, found {}", expected, found)
+            }
+            ConversionError::OutOfRange { value, target } => {
+                write!(f, "integer {} out of range for {}", value, target)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+fn mismatch<T>(expected: &'static str, found: &Object) -> Result<T, ConversionError> {
+    Err(ConversionError::TypeMismatch {
+        expected,
+        found: found.type_val(),
+    })
+}
+
+/// Rust values that can be handed to scripts.
+///
+/// ```
+/// use thorsten_interpreter::{convert::IntoMonkey, object::Object};
+///
+/// assert_eq!(
+///     vec![Some(1), None].into_monkey(),
+///     Object::Array(vec![Object::Integer(1), Object::Null])
+/// );
+/// ```
+pub trait IntoMonkey {
+    fn into_monkey(self) -> Object;
+}
+
+/// Rust values that can be read back from script results.
+///
+/// ```
+/// use std::collections::HashMap;
+///
+/// use thorsten_interpreter::{convert::FromMonkey, interpreter::Interpreter};
+///
+/// let mut interpreter = Interpreter::new();
+/// interpreter.set_global("scores", HashMap::from([("ada".to_string(), 3)]));
+/// let scores = interpreter.get_global("scores").unwrap();
+///
+/// let scores = HashMap::<String, i64>::from_monkey(scores)?;
+/// assert_eq!(scores["ada"], 3);
+///
+/// let err = bool::from_monkey(interpreter.eval_str("1 + 1")?).unwrap_err();
+/// assert_eq!(err.to_string(), "type mismatch: expected BOOLEAN, found INTEGER");
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub trait FromMonkey: Sized {
+    fn from_monkey(obj: Object) -> Result<Self, ConversionError>;
+}
+
+/// Rust values usable as keys of converted hashes.
+pub trait MonkeyHashKey: Sized {
+    fn into_hash_key(self) -> HashKey;
+    fn from_hash_key(key: HashKey) -> Result<Self, ConversionError>;
+}
+
+impl IntoMonkey for Object {
+    fn into_monkey(self) -> Object {
+        self
+    }
+}
+
+impl FromMonkey for Object {
+    fn from_monkey(obj: Object) -> Result<Self, ConversionError> {
+        Ok(obj)
+    }
+}
+
+impl IntoMonkey for i64 {
+    fn into_monkey(self) -> Object {
+        Object::Integer(self)
+    }
+}
+
+impl FromMonkey for i64 {
+    fn from_monkey(obj: Object) -> Result<Self, ConversionError> {
+        match obj {
+            Object::Integer(n) => Ok(n),
+            obj => mismatch("INTEGER", &obj),
+        }
+    }
+}
+
+macro_rules! impl_integer {
+    ($($ty:ty),*) => {
+        $(
+            impl IntoMonkey for $ty {
+                fn into_monkey(self) -> Object {
+                    Object::Integer(self as i64)
+                }
+            }
+
+            impl FromMonkey for $ty {
+                fn from_monkey(obj: Object) -> Result<Self, ConversionError> {
+                    let value = i64::from_monkey(obj)?;
+                    <$ty>::try_from(value).map_err(|_| ConversionError::OutOfRange {
+                        value,
+                        target: stringify!($ty),
+                    })
+                }
+            }
+        )*
+    };
+}
+
+impl_integer!(i8, i16, i32, u8, u16, u32);
+
+impl IntoMonkey for bool {
+    fn into_monkey(self) -> Object {
+        Object::Boolean(self)
+    }
+}
+
+impl FromMonkey for bool {
+    fn from_monkey(obj: Object) -> Result<Self, ConversionError> {
+        match obj {
+            Object::Boolean(b) => Ok(b),
+            obj => mismatch("BOOLEAN", &obj),
+        }
+    }
+}
+
+impl IntoMonkey for String {
+    fn into_monkey(self) -> Object {
+        Object::String(self)
+    }
+}
+
+impl IntoMonkey for &str {
+    fn into_monkey(self) -> Object {
+        Object::String(self.to_string())
+    }
+}
+
+impl FromMonkey for String {
+    fn from_monkey(obj: Object) -> Result<Self, ConversionError> {
+        match obj {
+            Object::String(s) => Ok(s),
+            obj => mismatch("STRING", &obj),
+        }
+    }
+}
+
+impl<T: IntoMonkey> IntoMonkey for Option<T> {
+    fn into_monkey(self) -> Object {
+        match self {
+            Some(value) => value.into_monkey(),
+            None => Object::Null,
+        }
+    }
+}
+
+impl<T: FromMonkey> FromMonkey for Option<T> {
+    fn from_monkey(obj: Object) -> Result<Self, ConversionError> {
+        match obj {
+            Object::Null => Ok(None),
+            obj => T::from_monkey(obj).map(Some),
+        }
+    }
+}
+
+impl<T: IntoMonkey> IntoMonkey for Vec<T> {
+    fn into_monkey(self) -> Object {
+        Object::Array(self.into_iter().map(IntoMonkey::into_monkey).collect())
+    }
+}
+
+impl<T: FromMonkey> FromMonkey for Vec<T> {
+    fn from_monkey(obj: Object) -> Result<Self, ConversionError> {
+        match obj {
+            Object::Array(elements) => elements.into_iter().map(T::from_monkey).collect(),
+            obj => mismatch("ARRAY", &obj),
+        }
+    }
+}
+
+impl MonkeyHashKey for i64 {
+    fn into_hash_key(self) -> HashKey {
+        HashKey::Integer(self)
+    }
+
+    fn from_hash_key(key: HashKey) -> Result<Self, ConversionError> {
+        i64::from_monkey(key.into())
+    }
+}
+
+impl MonkeyHashKey for bool {
+    fn into_hash_key(self) -> HashKey {
+        HashKey::Boolean(self)
+    }
+
+    fn from_hash_key(key: HashKey) -> Result<Self, ConversionError> {
+        bool::from_monkey(key.into())
+    }
+}
+
+impl MonkeyHashKey for String {
+    fn into_hash_key(self) -> HashKey {
+        HashKey::String(self)
+    }
+
+    fn from_hash_key(key: HashKey) -> Result<Self, ConversionError> {
+        String::from_monkey(key.into())
+    }
+}
+
+impl<K, V> IntoMonkey for HashMap<K, V>
+where
+    K: MonkeyHashKey,
+    V: IntoMonkey,
+{
+    fn into_monkey(self) -> Object {
+        Object::Hash(
+            self.into_iter()
+                .map(|(key, value)| (key.into_hash_key(), value.into_monkey()))
+                .collect::<BTreeMap<_, _>>(),
+        )
+    }
+}
+
+impl<K, V> FromMonkey for HashMap<K, V>
+where
+    K: MonkeyHashKey + Eq + Hash,
+    V: FromMonkey,
+{
+    fn from_monkey(obj: Object) -> Result<Self, ConversionError> {
+        match obj {
+            Object::Hash(pairs) => pairs
+                .into_iter()
+                .map(|(key, value)| Ok((K::from_hash_key(key)?, V::from_monkey(value)?)))
+                .collect(),
+            obj => mismatch("HASH", &obj),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::object::Object;
+
+    use super::{ConversionError, FromMonkey, IntoMonkey};
+
+    fn round_trip<T>(value: T) -> T
+    where
+        T: IntoMonkey + FromMonkey + Clone,
+    {
+        T::from_monkey(value.into_monkey()).unwrap()
+    }
+
+    #[test]
+    fn test_round_trips() {
+        assert_eq!(round_trip(-5i64), -5);
+        assert_eq!(round_trip(7u8), 7);
+        assert!(round_trip(true));
+        assert_eq!(round_trip("monkey".to_string()), "monkey");
+        assert_eq!(round_trip(Some(3i32)), Some(3));
+        assert_eq!(round_trip(None::<i32>), None);
+        assert_eq!(
+            round_trip(vec![vec![1i64], vec![]]),
+            vec![vec![1i64], vec![]]
+        );
+
+        let map = HashMap::from([(1i64, "one".to_string()), (2, "two".to_string())]);
+        assert_eq!(round_trip(map.clone()), map);
+    }
+
+    #[test]
+    fn test_conversion_errors() {
+        assert_eq!(
+            i64::from_monkey(Object::String("5".to_string())),
+            Err(ConversionError::TypeMismatch {
+                expected: "INTEGER",
+                found: "STRING"
+            })
+        );
+        assert_eq!(
+            u8::from_monkey(Object::Integer(256)),
+            Err(ConversionError::OutOfRange {
+                value: 256,
+                target: "u8"
+            })
+        );
+        assert_eq!(
+            Vec::<bool>::from_monkey(Object::Array(vec![Object::Boolean(true), Object::Null])),
+            Err(ConversionError::TypeMismatch {
+                expected: "BOOLEAN",
+                found: "NULL"
+            })
+        );
+        assert_eq!(
+            HashMap::<String, i64>::from_monkey(HashMap::from([(1i64, 1i64)]).into_monkey()),
+            Err(ConversionError::TypeMismatch {
+                expected: "STRING",
+                found: "INTEGER"
+            })
+        );
+        assert_eq!(
+            Vec::<i64>::from_monkey(Object::Integer(1))
+                .unwrap_err()
+                .to_string(),
+            "type mismatch: expected ARRAY, found INTEGER"
+        );
+    }
+}
@@ -0,0 +1,619 @@
+//! Debug adapter for Monkey, speaking the Debug Adapter Protocol over any reader and
+//! writer, as the `monkey-dap` binary does over stdin and stdout.
+//!
+//! The adapter debugs the one program named by the `launch` request, as a single
+//! thread. The program starts once the client sends `configurationDone`; while it is
+//! paused the adapter answers stack trace, scopes and variables requests, and its
+//! `puts` output is sent as `output` events.
+
+use std::{
+    fmt::Display,
+    fs,
+    io::{self, BufRead, Write},
+    sync::{
+        atomic::{AtomicBool, AtomicI64, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+
+use crate::{
+    debugger::{Breakpoints, DebugFrontend, Debugger, Pause, PauseReason, Resume},
+    diagnostic::line_col,
+    interpreter::Interpreter,
+    lsp::{read_message, write_message},
+    shared_io::{SharedReader, SharedWriter},
+};
+
+const THREAD_ID: i64 = 1;
+
+/// Serves the client on `reader` and `writer` until it disconnects or closes the input.
+pub fn run<R, W>(reader: R, writer: W) -> Result<()>
+where
+    R: BufRead + Send + 'static,
+    W: Write + Send + 'static,
+{
+    let connection = Connection {
+        reader: SharedReader::new(reader),
+        writer: SharedWriter::new(writer),
+        seq: Arc::new(AtomicI64::new(1)),
+    };
+    let mut breakpoints = Breakpoints::default();
+    let mut launch = None;
+    let disconnected = Arc::new(AtomicBool::new(false));
+
+    while let Some(request) = connection.receive()? {
+        let arguments = &request["arguments"];
+        match command(&request) {
+            "initialize" => {
+                connection.respond(
+                    &request,
+                    json!({
+                        "supportsConfigurationDoneRequest": true,
+                        "supportsFunctionBreakpoints": true,
+                    }),
+                )?;
+                connection.event("initialized", json!({}))?;
+            }
+            "launch" => {
+                let Some(program) = arguments["program"].as_str() else {
+                    connection.fail(&request, "launch expects a program")?;
+                    continue;
+                };
+                match fs::read_to_string(program) {
+                    Ok(source) => {
+                        let stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+                        launch = Some((program.to_string(), source, stop_on_entry));
+                        connection.respond(&request, json!({}))?;
+                    }
+                    Err(err) => {
+                        connection.fail(&request, format!("unable to read {}: {}", program, err))?
+                    }
+                }
+            }
+            "configurationDone" => {
+                connection.respond(&request, json!({}))?;
+                let Some((program, source, stop_on_entry)) = launch.take() else {
+                    continue;
+                };
+                let debugger = Debugger::new(Frontend {
+                    connection: connection.clone(),
+                    program,
+                    source: source.clone(),
+                    disconnected: disconnected.clone(),
+                })
+                .with_breakpoints(breakpoints.clone());
+                let debugger = if stop_on_entry {
+                    debugger.with_stop_on_entry()
+                } else {
+                    debugger
+                };
+                execute(&connection, &source, debugger)?;
+                if disconnected.load(Ordering::Relaxed) {
+                    break;
+                }
+            }
+            "disconnect" => {
+                connection.respond(&request, json!({}))?;
+                break;
+            }
+            command => {
+                if !configure(&connection, &request, &mut breakpoints)? {
+                    connection.fail(&request, format!("unexpected request {}", command))?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Runs the program to completion, reporting how it ended.
+fn execute<R, W>(connection: &Connection<R, W>, source: &str, debugger: Debugger) -> Result<()>
+where
+    R: BufRead + Send + 'static,
+    W: Write + Send + 'static,
+{
+    // the program's stdin is the protocol stream, so `gets` reads nothing
+    let output = Output {
+        connection: connection.clone(),
+        line: Vec::new(),
+    };
+    let mut interpreter = Interpreter::new()
+        .with_input(io::empty())
+        .with_output(output);
+    let result = interpreter.debug_str(source, debugger);
+
+    if let Err(err) = &result {
+        connection.event(
+            "output",
+            json!({ "category": "stderr", "output": format!("Error {}\n", err) }),
+        )?;
+    }
+    let exit_code = if result.is_ok() { 0 } else { 1 };
+    connection.event("exited", json!({ "exitCode": exit_code }))?;
+    connection.event("terminated", json!({}))
+}
+
+fn command(request: &Value) -> &str {
+    request["command"].as_str().unwrap_or("")
+}
+
+/// Answers the requests that are handled the same way whether or not the program is
+/// running, false for any other request.
+fn configure<R, W>(
+    connection: &Connection<R, W>,
+    request: &Value,
+    breakpoints: &mut Breakpoints,
+) -> Result<bool>
+where
+    R: BufRead,
+    W: Write,
+{
+    let arguments = &request["arguments"];
+    let requested = || {
+        arguments["breakpoints"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default()
+    };
+    match command(request) {
+        "setBreakpoints" => {
+            // there is only one source, so the breakpoints replace all the line ones
+            breakpoints.lines = requested()
+                .iter()
+                .filter_map(|breakpoint| breakpoint["line"].as_u64())
+                .map(|line| line as usize)
+                .collect();
+            let verified: Vec<_> = requested()
+                .iter()
+                .map(|breakpoint| json!({ "verified": true, "line": breakpoint["line"] }))
+                .collect();
+            connection.respond(request, json!({ "breakpoints": verified }))?;
+        }
+        "setFunctionBreakpoints" => {
+            breakpoints.functions = requested()
+                .iter()
+                .filter_map(|breakpoint| breakpoint["name"].as_str())
+                .map(str::to_string)
+                .collect();
+            let verified: Vec<_> = requested()
+                .iter()
+                .map(|_| json!({ "verified": true }))
+                .collect();
+            connection.respond(request, json!({ "breakpoints": verified }))?;
+        }
+        "setExceptionBreakpoints" => connection.respond(request, json!({}))?,
+        "threads" => connection.respond(
+            request,
+            json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] }),
+        )?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+// Both ends of the protocol stream, shared by the request loop, the paused program and
+// its output.
+struct Connection<R, W> {
+    reader: SharedReader<R>,
+    writer: SharedWriter<W>,
+    seq: Arc<AtomicI64>,
+}
+
+impl<R, W> Clone for Connection<R, W> {
+    fn clone(&self) -> Self {
+        Self {
+            reader: self.reader.clone(),
+            writer: self.writer.clone(),
+            seq: self.seq.clone(),
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Connection<R, W> {
+    /// The next request, `None` at the end of the input.
+    fn receive(&self) -> Result<Option<Value>> {
+        let Some(body) = read_message(&mut *self.reader.lock())? else {
+            return Ok(None);
+        };
+        serde_json::from_str(&body)
+            .map(Some)
+            .context("message body is not JSON")
+    }
+
+    fn send(&self, mut message: Value) -> Result<()> {
+        message["seq"] = self.seq.fetch_add(1, Ordering::Relaxed).into();
+        write_message(&mut *self.writer.lock(), &message)
+    }
+
+    fn respond(&self, request: &Value, body: Value) -> Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        }))
+    }
+
+    fn fail(&self, request: &Value, message: impl Display) -> Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message.to_string(),
+        }))
+    }
+
+    fn event(&self, event: &str, body: Value) -> Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+}
+
+// Serves the client while the program is paused.
+struct Frontend<R, W> {
+    connection: Connection<R, W>,
+    program: String,
+    source: String,
+    disconnected: Arc<AtomicBool>,
+}
+
+impl<R: BufRead, W: Write> Frontend<R, W> {
+    fn stack_trace(&self, pause: &Pause) -> Value {
+        let names = pause
+            .frames
+            .iter()
+            .rev()
+            .map(|frame| frame.function.as_str())
+            .chain(["<main>"]);
+        // each frame is paused at the call site of the one it called
+        let offsets = [pause.span.start].into_iter().chain(
+            pause
+                .frames
+                .iter()
+                .rev()
+                .map(|frame| frame.call_site.unwrap_or_default().start),
+        );
+        let frames: Vec<_> = names
+            .zip(offsets)
+            .enumerate()
+            .map(|(id, (name, offset))| {
+                let (line, column) = line_col(&self.source, offset);
+                json!({
+                    "id": id,
+                    "name": name,
+                    "line": line,
+                    "column": column,
+                    "source": { "path": self.program },
+                })
+            })
+            .collect();
+        json!({ "totalFrames": frames.len(), "stackFrames": frames })
+    }
+
+    fn scopes(&self, pause: &Pause, frame_id: Option<u64>) -> Value {
+        // only the innermost frame's environment is known
+        if frame_id != Some(0) {
+            return json!({ "scopes": [] });
+        }
+        let count = pause.scopes().len();
+        let scopes: Vec<_> = (0..count)
+            .map(|idx| {
+                let name = if idx + 1 == count {
+                    "Globals"
+                } else if idx == 0 {
+                    "Locals"
+                } else {
+                    "Closure"
+                };
+                json!({ "name": name, "variablesReference": idx + 1, "expensive": false })
+            })
+            .collect();
+        json!({ "scopes": scopes })
+    }
+
+    fn variables(&self, pause: &Pause, reference: Option<u64>) -> Value {
+        let scope = reference
+            .and_then(|reference| pause.scopes().into_iter().nth(reference as usize - 1))
+            .unwrap_or_default();
+        let variables: Vec<_> = scope
+            .iter()
+            .map(|(name, value)| {
+                json!({ "name": name, "value": value.inspect(), "variablesReference": 0 })
+            })
+            .collect();
+        json!({ "variables": variables })
+    }
+}
+
+impl<R, W> DebugFrontend for Frontend<R, W>
+where
+    R: BufRead + Send,
+    W: Write + Send,
+{
+    fn paused(&mut self, pause: &Pause, breakpoints: &mut Breakpoints) -> Result<Resume> {
+        let reason = match pause.reason {
+            PauseReason::Entry => "entry",
+            PauseReason::Breakpoint => "breakpoint",
+            PauseReason::FunctionBreakpoint => "function breakpoint",
+            PauseReason::Step => "step",
+        };
+        self.connection.event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+        )?;
+
+        while let Some(request) = self.connection.receive()? {
+            let arguments = &request["arguments"];
+            let resume = match command(&request) {
+                "continue" => Resume::Continue,
+                "next" => Resume::StepOver,
+                "stepIn" => Resume::StepIn,
+                "stepOut" => Resume::StepOut,
+                "disconnect" => {
+                    self.disconnected.store(true, Ordering::Relaxed);
+                    Resume::Stop
+                }
+                "stackTrace" => {
+                    let body = self.stack_trace(pause);
+                    self.connection.respond(&request, body)?;
+                    continue;
+                }
+                "scopes" => {
+                    let body = self.scopes(pause, arguments["frameId"].as_u64());
+                    self.connection.respond(&request, body)?;
+                    continue;
+                }
+                "variables" => {
+                    let reference = arguments["variablesReference"].as_u64().filter(|r| *r > 0);
+                    let body = self.variables(pause, reference);
+                    self.connection.respond(&request, body)?;
+                    continue;
+                }
+                command => {
+                    if !configure(&self.connection, &request, breakpoints)? {
+                        self.connection
+                            .fail(&request, format!("unexpected request {}", command))?;
+                    }
+                    continue;
+                }
+            };
+            let body = if resume == Resume::Continue {
+                json!({ "allThreadsContinued": true })
+            } else {
+                json!({})
+            };
+            self.connection.respond(&request, body)?;
+            return Ok(resume);
+        }
+        Ok(Resume::Stop)
+    }
+}
+
+// Sends what the program prints as `output` events, a line at a time.
+struct Output<R, W> {
+    connection: Connection<R, W>,
+    line: Vec<u8>,
+}
+
+impl<R: BufRead, W: Write> Write for Output<R, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        if buf.contains(&b'\n') {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.line.is_empty() {
+            return Ok(());
+        }
+        let output = String::from_utf8_lossy(&self.line).into_owned();
+        self.line.clear();
+        self.connection
+            .event("output", json!({ "category": "stdout", "output": output }))
+            .map_err(io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor};
+
+    use serde_json::{json, Value};
+
+    use super::run;
+    use crate::{
+        lsp::{read_message, write_message},
+        shared_io::SharedWriter,
+    };
+
+    // Runs the client requests through the adapter, returning what it sent back.
+    fn transcript(requests: &[(&str, Value)]) -> Vec<Value> {
+        let mut input = Vec::new();
+        for (seq, (command, arguments)) in requests.iter().enumerate() {
+            let request = json!({ "seq": seq + 1, "type": "request", "command": command, "arguments": arguments });
+            write_message(&mut input, &request).unwrap();
+        }
+        let output = SharedWriter::new(Vec::new());
+        run(Cursor::new(input), output.clone()).unwrap();
+
+        let output = output.lock().clone();
+        let mut reader = output.as_slice();
+        let mut messages = Vec::new();
+        while let Some(body) = read_message(&mut reader).unwrap() {
+            messages.push(serde_json::from_str(&body).unwrap());
+        }
+        messages
+    }
+
+    // The messages as `command` for responses and `!event` for events.
+    fn summary(messages: &[Value]) -> Vec<String> {
+        messages
+            .iter()
+            .map(|message| match message["type"].as_str() {
+                Some("event") => format!("!{}", message["event"].as_str().unwrap()),
+                _ => message["command"].as_str().unwrap().to_string(),
+            })
+            .collect()
+    }
+
+    fn find<'a>(messages: &'a [Value], command: &str) -> &'a Value {
+        messages
+            .iter()
+            .find(|message| message["command"] == command)
+            .unwrap()
+    }
+
+    const SOURCE: &str = "let add = fn(a, b) {\n  let sum = a + b;\n  sum\n};\nputs(add(1, 2));\n";
+
+    fn program(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("dap-{}-{}.monkey", name, std::process::id()));
+        fs::write(&path, SOURCE).unwrap();
+        path.display().to_string()
+    }
+
+    #[test]
+    fn test_breakpoint_session() {
+        let path = program("breakpoint");
+        let messages = transcript(&[
+            ("initialize", json!({ "adapterID": "monkey" })),
+            ("launch", json!({ "program": path })),
+            (
+                "setBreakpoints",
+                json!({ "source": { "path": path }, "breakpoints": [{ "line": 3 }] }),
+            ),
+            ("configurationDone", json!({})),
+            ("threads", json!({})),
+            ("stackTrace", json!({ "threadId": 1 })),
+            ("scopes", json!({ "frameId": 0 })),
+            ("variables", json!({ "variablesReference": 1 })),
+            ("continue", json!({ "threadId": 1 })),
+            ("disconnect", json!({})),
+        ]);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            summary(&messages),
+            [
+                "initialize",
+                "!initialized",
+                "launch",
+                "setBreakpoints",
+                "configurationDone",
+                "!stopped",
+                "threads",
+                "stackTrace",
+                "scopes",
+                "variables",
+                "continue",
+                "!output",
+                "!exited",
+                "!terminated",
+                "disconnect",
+            ]
+        );
+        let seqs: Vec<_> = messages
+            .iter()
+            .map(|message| message["seq"].as_i64().unwrap())
+            .collect();
+        assert_eq!(seqs, (1..=15).collect::<Vec<_>>());
+
+        assert_eq!(messages[5]["body"]["reason"], "breakpoint");
+        assert_eq!(
+            find(&messages, "setBreakpoints")["body"],
+            json!({ "breakpoints": [{ "verified": true, "line": 3 }] })
+        );
+        assert_eq!(
+            find(&messages, "stackTrace")["body"],
+            json!({
+                "totalFrames": 2,
+                "stackFrames": [
+                    { "id": 0, "name": "add", "line": 3, "column": 3, "source": { "path": path } },
+                    { "id": 1, "name": "<main>", "line": 5, "column": 6, "source": { "path": path } },
+                ],
+            })
+        );
+        assert_eq!(
+            find(&messages, "scopes")["body"]["scopes"],
+            json!([
+                { "name": "Locals", "variablesReference": 1, "expensive": false },
+                { "name": "Globals", "variablesReference": 2, "expensive": false },
+            ])
+        );
+        assert_eq!(
+            find(&messages, "variables")["body"]["variables"],
+            json!([
+                { "name": "a", "value": "1", "variablesReference": 0 },
+                { "name": "b", "value": "2", "variablesReference": 0 },
+                { "name": "sum", "value": "3", "variablesReference": 0 },
+            ])
+        );
+        assert_eq!(
+            messages[11]["body"],
+            json!({ "category": "stdout", "output": "3\n" })
+        );
+        assert_eq!(messages[12]["body"], json!({ "exitCode": 0 }));
+    }
+
+    #[test]
+    fn test_stepping_and_errors() {
+        let path = program("step");
+        let messages = transcript(&[
+            ("initialize", json!({})),
+            ("launch", json!({ "program": "/nonexistent.monkey" })),
+            ("launch", json!({ "program": path, "stopOnEntry": true })),
+            (
+                "setFunctionBreakpoints",
+                json!({ "breakpoints": [{ "name": "add" }] }),
+            ),
+            ("configurationDone", json!({})),
+            ("next", json!({ "threadId": 1 })),
+            ("evaluate", json!({ "expression": "a" })),
+            ("stepIn", json!({ "threadId": 1 })),
+            ("stepOut", json!({ "threadId": 1 })),
+            ("disconnect", json!({})),
+        ]);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            summary(&messages),
+            [
+                "initialize",
+                "!initialized",
+                "launch",
+                "launch",
+                "setFunctionBreakpoints",
+                "configurationDone",
+                "!stopped",
+                "next",
+                "!stopped",
+                "evaluate",
+                "stepIn",
+                "!stopped",
+                "stepOut",
+                "!output",
+                "!exited",
+                "!terminated",
+                "disconnect",
+            ]
+        );
+        assert_eq!(messages[2]["success"], false);
+        assert!(messages[2]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("unable to read /nonexistent.monkey"));
+        assert_eq!(messages[3]["success"], true);
+
+        let reasons: Vec<_> = [6, 8, 11]
+            .iter()
+            .map(|&idx| messages[idx]["body"]["reason"].as_str().unwrap())
+            .collect();
+        assert_eq!(reasons, ["entry", "step", "function breakpoint"]);
+        assert_eq!(messages[9]["message"], "unexpected request evaluate");
+    }
+}
@@ -0,0 +1,346 @@
+//! Step debugging: a [`Debugger`] given to [`Interpreter::debug_str`] pauses evaluation
+//! at breakpoints and after steps, handing control to a [`DebugFrontend`] such as the
+//! REPL's `:debug` command or the `monkey-dap` adapter.
+//!
+//! [`Interpreter::debug_str`]: crate::interpreter::Interpreter::debug_str
+
+use std::collections::BTreeSet;
+
+use anyhow::{bail, Result};
+
+use crate::{
+    diagnostic::line_col,
+    environment::Environment,
+    evaluator::{EvalError, Frame},
+    object::Object,
+    token::Span,
+};
+
+/// How evaluation goes on after a pause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Until the next breakpoint.
+    Continue,
+    /// To the next statement, inside the functions called by this one.
+    StepIn,
+    /// To the next statement of the current function or the ones it returns to.
+    StepOver,
+    /// Until the current function returns.
+    StepOut,
+    /// Abandon the evaluation, which fails with [`EvalError::Interrupted`].
+    Stop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseReason {
+    Entry,
+    Breakpoint,
+    FunctionBreakpoint,
+    Step,
+}
+
+/// Where evaluation pauses without being asked to step.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Breakpoints {
+    /// One based source lines, the first statement starting on each pauses.
+    pub lines: BTreeSet<usize>,
+    /// Names of functions that pause when called.
+    pub functions: BTreeSet<String>,
+}
+
+/// The state of a paused evaluation.
+pub struct Pause<'a> {
+    pub reason: PauseReason,
+    /// One based line of the statement or call about to be evaluated.
+    pub line: usize,
+    pub span: Span,
+    /// Calls in progress, innermost last.
+    pub frames: &'a [Frame],
+    env: &'a Environment,
+}
+
+impl Pause<'_> {
+    /// Bindings of every scope visible from the paused code, innermost first and the
+    /// globals last.
+    pub fn scopes(&self) -> Vec<Vec<(String, Object)>> {
+        let mut scopes = Vec::new();
+        let mut env = Some(self.env.clone());
+        while let Some(scope) = env {
+            scopes.push(scope.bindings());
+            env = scope.outer();
+        }
+        scopes
+    }
+
+    /// The value `name` has for the paused code.
+    pub fn lookup(&self, name: &str) -> Option<Object> {
+        self.env.get(name)
+    }
+}
+
+/// Decides what happens at each pause, typically by asking the user.
+pub trait DebugFrontend: Send {
+    /// Called whenever evaluation pauses, with the breakpoints to use from then on.
+    fn paused(&mut self, pause: &Pause, breakpoints: &mut Breakpoints) -> Result<Resume>;
+}
+
+pub struct Debugger {
+    frontend: Box<dyn DebugFrontend>,
+    breakpoints: Breakpoints,
+    // evaluated source, to turn spans into lines
+    source: String,
+    stop_on_entry: bool,
+    resume: Resume,
+    // number of frames when the frontend last resumed
+    resume_depth: usize,
+    // line and call depth of the last statement, so a breakpoint pauses once per visit
+    last_line: Option<(usize, usize)>,
+}
+
+impl Debugger {
+    pub fn new(frontend: impl DebugFrontend + 'static) -> Self {
+        Self {
+            frontend: Box::new(frontend),
+            breakpoints: Breakpoints::default(),
+            source: String::new(),
+            stop_on_entry: false,
+            resume: Resume::Continue,
+            resume_depth: 0,
+            last_line: None,
+        }
+    }
+
+    pub fn with_breakpoints(mut self, breakpoints: Breakpoints) -> Self {
+        self.breakpoints = breakpoints;
+        self
+    }
+
+    /// Pauses before the first statement.
+    pub fn with_stop_on_entry(mut self) -> Self {
+        self.stop_on_entry = true;
+        self.resume = Resume::StepIn;
+        self
+    }
+
+    pub(crate) fn with_source(mut self, source: &str) -> Self {
+        self.source = source.to_string();
+        self
+    }
+
+    pub(crate) fn before_statement(
+        &mut self,
+        span: Span,
+        env: &Environment,
+        frames: &[Frame],
+    ) -> Result<()> {
+        let (line, _) = line_col(&self.source, span.start);
+        let depth = frames.len();
+        let new_line = self.last_line != Some((line, depth));
+        self.last_line = Some((line, depth));
+
+        let stepped = match self.resume {
+            Resume::StepIn => true,
+            Resume::StepOver => depth <= self.resume_depth,
+            Resume::StepOut => depth < self.resume_depth,
+            Resume::Continue | Resume::Stop => false,
+        };
+        let reason = if stepped && self.stop_on_entry {
+            PauseReason::Entry
+        } else if stepped {
+            PauseReason::Step
+        } else if new_line && self.breakpoints.lines.contains(&line) {
+            PauseReason::Breakpoint
+        } else {
+            return Ok(());
+        };
+        self.pause(reason, line, span, env, frames)
+    }
+
+    pub(crate) fn before_call(
+        &mut self,
+        frame: &Frame,
+        env: &Environment,
+        frames: &[Frame],
+    ) -> Result<()> {
+        if !self.breakpoints.functions.contains(&frame.function) {
+            return Ok(());
+        }
+        // only the host calls functions without a call site, never while debugging
+        let span = frame.call_site.unwrap_or_default();
+        let (line, _) = line_col(&self.source, span.start);
+        self.pause(PauseReason::FunctionBreakpoint, line, span, env, frames)
+    }
+
+    fn pause(
+        &mut self,
+        reason: PauseReason,
+        line: usize,
+        span: Span,
+        env: &Environment,
+        frames: &[Frame],
+    ) -> Result<()> {
+        self.stop_on_entry = false;
+        let pause = Pause {
+            reason,
+            line,
+            span,
+            frames,
+            env,
+        };
+        let resume = self.frontend.paused(&pause, &mut self.breakpoints)?;
+        if resume == Resume::Stop {
+            bail!(EvalError::Interrupted);
+        }
+        self.resume = resume;
+        self.resume_depth = frames.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::Result;
+
+    use super::{Breakpoints, DebugFrontend, Debugger, Pause, Resume};
+    use crate::{interpreter::Interpreter, object::Object};
+
+    // Answers each pause with the next scripted command, recording where it paused.
+    struct Script {
+        commands: Vec<Resume>,
+        pauses: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl DebugFrontend for Script {
+        fn paused(&mut self, pause: &Pause, breakpoints: &mut Breakpoints) -> Result<Resume> {
+            let functions: Vec<_> = pause.frames.iter().map(|f| f.function.as_str()).collect();
+            self.pauses.lock().unwrap().push(format!(
+                "{:?} line {} in [{}]",
+                pause.reason,
+                pause.line,
+                functions.join(", ")
+            ));
+            breakpoints.functions.clear();
+            Ok(if self.commands.is_empty() {
+                Resume::Continue
+            } else {
+                self.commands.remove(0)
+            })
+        }
+    }
+
+    const SOURCE: &str = "let add = fn(a, b) {
+    let sum = a + b;
+    sum
+};
+let x = add(1, 2);
+let y = add(x, 3);
+y";
+
+    fn debug(debugger: impl FnOnce(Debugger) -> Debugger, commands: Vec<Resume>) -> Vec<String> {
+        let pauses = Arc::new(Mutex::new(Vec::new()));
+        let frontend = Script {
+            commands,
+            pauses: pauses.clone(),
+        };
+        let result = Interpreter::new().debug_str(SOURCE, debugger(Debugger::new(frontend)));
+        assert_eq!(result.unwrap(), Object::Integer(6));
+        let pauses = pauses.lock().unwrap().clone();
+        pauses
+    }
+
+    #[test]
+    fn test_stepping() {
+        use Resume::*;
+
+        let tests = vec![
+            (
+                vec![StepOver, StepOver, StepOver, StepOver],
+                vec![
+                    "Entry line 1 in []",
+                    "Step line 5 in []",
+                    "Step line 6 in []",
+                    "Step line 7 in []",
+                ],
+            ),
+            (
+                vec![StepOver, StepIn, StepIn, StepIn, StepIn],
+                vec![
+                    "Entry line 1 in []",
+                    "Step line 5 in []",
+                    "Step line 2 in [add]",
+                    "Step line 3 in [add]",
+                    "Step line 6 in []",
+                    "Step line 2 in [add]",
+                ],
+            ),
+            (
+                vec![StepOver, StepIn, StepOut, Continue],
+                vec![
+                    "Entry line 1 in []",
+                    "Step line 5 in []",
+                    "Step line 2 in [add]",
+                    "Step line 6 in []",
+                ],
+            ),
+        ];
+
+        for (commands, expected) in tests {
+            let pauses = debug(|debugger| debugger.with_stop_on_entry(), commands.clone());
+            assert_eq!(pauses, expected, "{:?}", commands);
+        }
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let breakpoints = Breakpoints {
+            lines: [3, 6].into(),
+            functions: ["add".to_string()].into(),
+        };
+        let pauses = debug(
+            |debugger| debugger.with_breakpoints(breakpoints),
+            Vec::new(),
+        );
+
+        // the frontend clears the function breakpoint when it first pauses
+        assert_eq!(
+            pauses,
+            [
+                "FunctionBreakpoint line 5 in []",
+                "Breakpoint line 3 in [add]",
+                "Breakpoint line 6 in []",
+                "Breakpoint line 3 in [add]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scopes_and_stop() {
+        // names in each scope
+        struct Inspect(Arc<Mutex<Vec<Vec<String>>>>);
+
+        impl DebugFrontend for Inspect {
+            fn paused(&mut self, pause: &Pause, _: &mut Breakpoints) -> Result<Resume> {
+                assert_eq!(pause.lookup("a"), Some(Object::Integer(1)));
+                *self.0.lock().unwrap() = pause
+                    .scopes()
+                    .into_iter()
+                    .map(|scope| scope.into_iter().map(|(name, _)| name).collect())
+                    .collect();
+                Ok(Resume::Stop)
+            }
+        }
+
+        let names = Arc::new(Mutex::new(Vec::new()));
+        let debugger = Debugger::new(Inspect(names.clone())).with_breakpoints(Breakpoints {
+            lines: [2].into(),
+            ..Default::default()
+        });
+        let err = Interpreter::new().debug_str(SOURCE, debugger).unwrap_err();
+        assert_eq!(err.to_string(), "evaluation interrupted");
+
+        let names = names.lock().unwrap();
+        assert_eq!(*names, [vec!["a", "b"], vec!["add"]]);
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::token::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Info => write!(f, "info"),
+        }
+    }
+}
+
+/// A problem found in the source, reported by the parser or the linter. Displays as
+/// its message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    /// Name of the lint rule that reported it, `None` for parse errors.
+    pub code: Option<&'static str>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+            code: None,
+        }
+    }
+
+    /// The diagnostic as `name:line:column: severity: message`, followed by the source
+    /// line with the span underlined.
+    ///
+    /// ```
+    /// use thorsten_interpreter::{diagnostic::Diagnostic, token::Span};
+    ///
+    /// let diagnostic = Diagnostic::error("identifier expected", Span { start: 4, end: 5 });
+    /// assert_eq!(
+    ///     diagnostic.render("let = 1;", "main.mk"),
+    ///     "main.mk:1:5: error: identifier expected\n  let = 1;\n      ^\n"
+    /// );
+    /// ```
+    pub fn render(&self, source: &str, name: &str) -> String {
+        let (line, column) = line_col(source, self.span.start);
+        let mut out = format!(
+            "{}:{}:{}: {}: {}",
+            name, line, column, self.severity, self.message
+        );
+        if let Some(code) = self.code {
+            out.push_str(&format!(" [{}]", code));
+        }
+        out.push('\n');
+
+        // errors at the end of the input point just past it
+        let start = self.span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |idx| idx + 1);
+        let text = source[line_start..].lines().next().unwrap_or("");
+        // spans over several lines are underlined to the end of the first one
+        let end = self.span.end.clamp(start, line_start + text.len());
+        let width = source
+            .get(start..end)
+            .map_or(0, |underlined| underlined.chars().count())
+            .max(1);
+        out.push_str(&format!(
+            "  {}\n  {}{}\n",
+            text,
+            " ".repeat(column - 1),
+            "^".repeat(width)
+        ));
+        out
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// One based line and column, in characters, of the byte `offset` in `source`.
+pub fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+    let line = before.matches('\n').count() + 1;
+    (line, before[line_start..].chars().count() + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{line_col, Diagnostic, Severity};
+    use crate::token::Span;
+
+    #[test]
+    fn test_line_col() {
+        let source = "let a = 1;\nlet é = a;\n";
+        let tests = vec![
+            (0, (1, 1)),
+            (4, (1, 5)),
+            (11, (2, 1)),
+            (17, (2, 6)),
+            (99, (3, 1)),
+        ];
+
+        for (offset, expected) in tests {
+            assert_eq!(line_col(source, offset), expected, "{}", offset);
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let source = "let a = 1;\nif (true) {\n  a\n}";
+        let diagnostic = Diagnostic {
+            severity: Severity::Warning,
+            message: "condition is always true".to_string(),
+            span: Span { start: 15, end: 19 },
+            code: Some("constant-condition"),
+        };
+
+        assert_eq!(
+            diagnostic.render(source, "<stdin>"),
+            "<stdin>:2:5: warning: condition is always true [constant-condition]\n  if (true) {\n      ^^^^\n"
+        );
+    }
+}
@@ -1,28 +1,240 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak},
+};
 
-use crate::object::Object;
+use crate::object::{ControlFlow, Flow, Object};
 
-#[derive(Debug, Default, Clone, PartialEq, Eq)]
-pub struct Environment {
+#[derive(Default)]
+struct Scope {
     store: HashMap<String, Object>,
-    outer: Option<Box<Environment>>,
+    outer: Option<Environment>,
+}
+
+// A function stored in the scope it was created in, as `let f = fn() { .. }` does, would
+// keep that scope alive through a cycle, so the stored copy only refers to it weakly.
+// Copies read back out of the scope refer to it strongly again, weak references never
+// leave the scope.
+#[derive(Clone)]
+enum ScopeRef {
+    Strong(Arc<RwLock<Scope>>),
+    Weak(Weak<RwLock<Scope>>),
+}
+
+/// Handle to a scope shared between the evaluator and every closure created in it, so
+/// a function bound with `let` can see itself when called recursively. Closures can be
+/// called from several threads at once, so the scope sits behind a lock.
+#[derive(Clone)]
+pub struct Environment {
+    scope: ScopeRef,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            scope: ScopeRef::Strong(Arc::default()),
+        }
+    }
 }
 
 impl Environment {
     pub fn new_with_outer(env: &Environment) -> Self {
         Self {
-            store: HashMap::new(),
-            outer: Some(Box::new(env.clone())),
+            scope: ScopeRef::Strong(Arc::new(RwLock::new(Scope {
+                store: HashMap::new(),
+                outer: Some(env.clone()),
+            }))),
+        }
+    }
+
+    fn arc(&self) -> &Arc<RwLock<Scope>> {
+        match &self.scope {
+            ScopeRef::Strong(scope) => scope,
+            ScopeRef::Weak(_) => unreachable!("weak environments never leave their scope"),
+        }
+    }
+
+    fn ptr(&self) -> *const RwLock<Scope> {
+        match &self.scope {
+            ScopeRef::Strong(scope) => Arc::as_ptr(scope),
+            ScopeRef::Weak(scope) => Weak::as_ptr(scope),
+        }
+    }
+
+    // Makes the functions in `value` created in this scope refer to it weakly, or strongly
+    // again with `strong`.
+    fn relink(&self, value: &mut Object, strong: bool) {
+        let mut pending = vec![value];
+        while let Some(value) = pending.pop() {
+            match value {
+                Object::Function(func) if func.env.ptr() == self.ptr() => {
+                    func.env.scope = if strong {
+                        ScopeRef::Strong(self.arc().clone())
+                    } else {
+                        ScopeRef::Weak(Arc::downgrade(self.arc()))
+                    };
+                }
+                Object::Array(elements) => pending.extend(elements.iter_mut()),
+                Object::Hash(pairs) => pending.extend(pairs.values_mut()),
+                Object::Ok(value) | Object::Err(value) => pending.push(value),
+                _ => {}
+            }
         }
     }
 
-    pub fn get(&self, k: &str) -> Option<&Object> {
-        self.store
-            .get(k)
-            .or_else(|| self.outer.as_ref().and_then(|out| out.get(k)))
+    // a panicking host function cannot leave a scope half updated, so poisoning is ignored
+    fn read(&self) -> RwLockReadGuard<'_, Scope> {
+        self.arc().read().unwrap_or_else(PoisonError::into_inner)
     }
 
-    pub fn set(&mut self, k: String, v: Object) -> Option<Object> {
-        self.store.insert(k, v)
+    fn write(&self) -> RwLockWriteGuard<'_, Scope> {
+        self.arc().write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn get(&self, k: &str) -> Option<Object> {
+        let scope = self.read();
+        match scope.store.get(k) {
+            Some(value) => {
+                let mut value = value.clone();
+                self.relink(&mut value, true);
+                Some(value)
+            }
+            None => scope.outer.as_ref().and_then(|out| out.get(k)),
+        }
+    }
+
+    /// Bindings of this scope, without the outer ones, sorted by name.
+    pub fn bindings(&self) -> Vec<(String, Object)> {
+        let mut bindings: Vec<_> = self
+            .read()
+            .store
+            .iter()
+            .map(|(k, v)| {
+                let mut v = v.clone();
+                self.relink(&mut v, true);
+                (k.clone(), v)
+            })
+            .collect();
+        bindings.sort_by(|(a, _), (b, _)| a.cmp(b));
+        bindings
+    }
+
+    /// The scope this one was created in, `None` for the globals.
+    pub fn outer(&self) -> Option<Environment> {
+        self.read().outer.clone()
+    }
+
+    pub fn set(&mut self, k: String, mut v: Object) -> Option<Object> {
+        self.relink(&mut v, false);
+        let mut old = self.write().store.insert(k, v)?;
+        self.relink(&mut old, true);
+        Some(old)
+    }
+}
+
+// A scope drops the closures stored in it, whose scopes drop theirs in turn: a chain of
+// them built by a long loop would overflow the native stack if every scope was dropped
+// inside the previous one. The values are taken apart here instead, one after another.
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let mut values: Vec<Object> = self.store.drain().map(|(_, value)| value).collect();
+        let mut scopes: Vec<Environment> = self.outer.take().into_iter().collect();
+        loop {
+            if let Some(value) = values.pop() {
+                match value {
+                    Object::Function(func) => scopes.push(func.env),
+                    Object::Array(elements) => values.extend(elements),
+                    Object::Hash(pairs) => values.extend(pairs.into_values()),
+                    Object::Ok(value)
+                    | Object::Err(value)
+                    | Object::ControlFlow(ControlFlow(Flow::Return(value))) => values.push(*value),
+                    Object::ControlFlow(ControlFlow(Flow::TailCall(call))) => {
+                        scopes.push(call.func.env);
+                        values.extend(call.arguments);
+                    }
+                    _ => {}
+                }
+            } else if let Some(env) = scopes.pop() {
+                // only the last reference takes the scope apart, it is then dropped empty
+                let ScopeRef::Strong(scope) = env.scope else {
+                    continue;
+                };
+                if let Some(scope) = Arc::into_inner(scope) {
+                    let mut scope = scope.into_inner().unwrap_or_else(PoisonError::into_inner);
+                    values.extend(scope.store.drain().map(|(_, value)| value));
+                    scopes.extend(scope.outer.take());
+                }
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+// A recursive function lives in the scope it captured, so walking the bindings would
+// never terminate: environments compare by identity and only print their names.
+impl PartialEq for Environment {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr() == other.ptr()
+    }
+}
+
+impl Eq for Environment {}
+
+impl Debug for Environment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let scope = self.read();
+        let mut names: Vec<_> = scope.store.keys().collect();
+        names.sort();
+        f.debug_struct("Environment")
+            .field("bindings", &names)
+            .field("outer", &scope.outer)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{evaluator::Evaluator, lexer::Lexer, object::Object, parser::Parser};
+
+    use super::{Environment, ScopeRef};
+
+    #[test]
+    fn test_scopes_are_dropped() {
+        // each program evaluates to a function whose scope holds nothing else alive
+        let tests = [
+            "let f = fn() { 1 }; f",
+            "let f = fn() { let g = fn() { 1 }; g }; f()",
+            "let f = fn() { let g = fn() { 1 }; let h = g; let all = [g, {\"h\": h}, ok(g)]; g }; f()",
+            "let f = fn() { let count = fn(n) { if (n == 0) { 0 } else { count(n - 1) } }; count }; f()",
+        ];
+
+        for input in tests {
+            let program = Parser::new(Lexer::new(input)).parse_program();
+            let mut env = Environment::default();
+            let Object::Function(func) = Evaluator::new().eval(program, &mut env).unwrap() else {
+                panic!("not a function: {}", input);
+            };
+            let ScopeRef::Strong(scope) = &func.env.scope else {
+                panic!("function holds its scope weakly: {}", input);
+            };
+            let scope = Arc::downgrade(scope);
+            drop((func, env));
+            assert!(scope.upgrade().is_none(), "scope leaked: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_functions_keep_their_scope_alive() {
+        let input = "let make = fn() { let g = fn() { 1 }; g }; let g = make(); g()";
+        let program = Parser::new(Lexer::new(input)).parse_program();
+
+        let obj = Evaluator::new()
+            .eval(program, &mut Environment::default())
+            .unwrap();
+        assert_eq!(obj, Object::Integer(1));
     }
 }
@@ -1,47 +1,461 @@
-use core::panic;
-
 use crate::{
-    ast::{BlockStatement, CallableExpression, Expression, Node, Operator, Program, Statement},
+    ast::{
+        ArmBody, BlockStatement, CallExpression, CallableExpression, DefaultPattern, Expression,
+        IfExpression, MatchExpression, Operator, Pattern, Program, Statement, TryExpression,
+    },
+    builtins::Builtin,
+    debugger::Debugger,
     environment::Environment,
-    object::{FunctionObj, Object},
+    object::{ControlFlow, ErrorObj, Flow, FunctionObj, HashKey, Object, TailCallObj},
+    token::Span,
+};
+use anyhow::{bail, Result};
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io::{self, BufRead, BufReader, Write},
+    mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
-use anyhow::{anyhow, bail, Result};
 
-pub struct Evaluator {}
+/// Nested (non tail) calls allowed before evaluation fails with
+/// [`EvalError::MaxCallDepthExceeded`]. The native stack grows on the heap as calls nest,
+/// so any depth is safe on any host thread.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+/// Arrays, hashes and results nested in each other deeper than this fail with
+/// [`EvalError::MaxNestingDepthExceeded`]. Such values are copied, compared and printed
+/// recursively, which must not overflow the native stack of the host thread.
+pub const MAX_NESTING_DEPTH: usize = 100;
+
+// Native stack left when a nested call moves to a fresh segment of `STACK_SEGMENT_BYTES`,
+// more than one level of calls, copying a value nested `MAX_NESTING_DEPTH` deep, uses in a
+// debug build.
+const STACK_RED_ZONE_BYTES: usize = 1024 * 1024;
+const STACK_SEGMENT_BYTES: usize = 4 * 1024 * 1024;
+
+// Steps between polls of the clock and the cancellation token, reading the clock on every
+// node would dominate evaluation time.
+const POLL_INTERVAL: u64 = 256;
+
+/// Shared flag that stops a running evaluation from another thread; the evaluator fails
+/// with [`EvalError::Interrupted`] the next time it polls the token.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Clears a previous cancellation so the token can be used for the next evaluation.
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Resources a single [`Evaluator::eval`] run may consume, unlimited by default. Running
+/// out fails the evaluation with [`EvalError::BudgetExceeded`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EvalBudget {
+    /// Expressions and statements evaluated.
+    pub max_steps: Option<u64>,
+    /// Approximate bytes of strings, arrays, hashes, closures and call scopes created.
+    pub max_allocated_bytes: Option<usize>,
+    /// Wall-clock time measured from the start of the run.
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BudgetLimit {
+    Steps(u64),
+    AllocatedBytes(usize),
+    Timeout(Duration),
+}
+
+/// Runtime failures that callers may want to tell apart from ordinary evaluation errors;
+/// they are raised through `anyhow` and can be recovered with `downcast_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    MaxCallDepthExceeded { function: String, limit: usize },
+    MaxNestingDepthExceeded { limit: usize },
+    BudgetExceeded(BudgetLimit),
+    Interrupted,
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalError::MaxCallDepthExceeded { function, limit } => write!(
+                f,
+                "maximum call depth exceeded: {} calls deep in {}",
+                limit, function
+            ),
+            EvalError::MaxNestingDepthExceeded { limit } => write!(
+                f,
+                "maximum nesting depth exceeded: values nested more than {} deep",
+                limit
+            ),
+            EvalError::BudgetExceeded(BudgetLimit::Steps(limit)) => {
+                write!(f, "evaluation budget exceeded: more than {} steps", limit)
+            }
+            EvalError::BudgetExceeded(BudgetLimit::AllocatedBytes(limit)) => write!(
+                f,
+                "evaluation budget exceeded: more than {} bytes allocated",
+                limit
+            ),
+            EvalError::BudgetExceeded(BudgetLimit::Timeout(timeout)) => {
+                write!(
+                    f,
+                    "evaluation budget exceeded: ran for more than {:?}",
+                    timeout
+                )
+            }
+            EvalError::Interrupted => write!(f, "evaluation interrupted"),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// Mistakes of the running script, raised through `anyhow` like [`EvalError`]. `catch`
+/// binds them as errors of the kind the variant is named after, and host functions may
+/// raise them too; any other error is caught as a `RuntimeError`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeError {
+    /// An operator, function or pattern applied to a value of the wrong type or shape.
+    TypeError(String),
+    /// A name that is not bound.
+    NameError(String),
+    /// Integer arithmetic that overflows or divides by zero.
+    ArithmeticError(String),
+}
+
+impl RuntimeError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RuntimeError::TypeError(_) => "TypeError",
+            RuntimeError::NameError(_) => "NameError",
+            RuntimeError::ArithmeticError(_) => "ArithmeticError",
+        }
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuntimeError::TypeError(message)
+            | RuntimeError::NameError(message)
+            | RuntimeError::ArithmeticError(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// A call to a Monkey function that has not returned yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Name of the `let` that bound the function, or else the name it was called by,
+    /// `anonymous function` for literals.
+    pub function: String,
+    /// `None` when the host called it.
+    pub call_site: Option<Span>,
+    /// One based line of the call site, in the source it was parsed from.
+    pub line: usize,
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.call_site {
+            Some(_) => write!(f, "in {}, called at line {}", self.function, self.line),
+            None => write!(f, "in {}, called by the host", self.function),
+        }
+    }
+}
+
+/// The calls a runtime error escaped from, attached to it as context so that it still
+/// downcasts to its own type. Displays as the error's message followed by the calls,
+/// innermost first.
+///
+/// ```
+/// use thorsten_interpreter::{interpreter::Interpreter, Traceback};
+///
+/// let source = "let add = fn(a, b) { a + b };\nlet twice = fn(x) { add(x, x) * 2 };\ntwice(true);";
+/// let err = Interpreter::new().eval_str(source).unwrap_err();
+///
+/// assert_eq!(err.downcast_ref::<Traceback>().unwrap().frames.len(), 2);
+/// assert_eq!(
+///     err.to_string(),
+///     "unknown operator: BOOLEAN + BOOLEAN\n  \
+///      in add, called at line 2\n  \
+///      in twice, called at line 3"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Traceback {
+    message: String,
+    /// Innermost call last.
+    pub frames: Vec<Frame>,
+}
+
+impl Display for Traceback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        let mut calls = self.frames.iter().rev().peekable();
+        while let Some(frame) = calls.next() {
+            // deep recursion shows up once
+            let mut repeated = 0;
+            while calls.next_if(|next| *next == frame).is_some() {
+                repeated += 1;
+            }
+            write!(f, "\n  {}", frame)?;
+            if repeated > 0 {
+                write!(f, " ({} more times)", repeated)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Raised by `?` on an `err`, to leave the expressions around it like an error would.
+#[derive(Debug)]
+struct EarlyReturn(Object);
+
+impl Display for EarlyReturn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "early return of {}", self.0)
+    }
+}
+
+impl std::error::Error for EarlyReturn {}
+
+pub struct Evaluator {
+    max_call_depth: usize,
+    call_depth: usize,
+    budget: EvalBudget,
+    steps: u64,
+    allocated_bytes: usize,
+    deadline: Option<Instant>,
+    cancellation: CancellationToken,
+    output: Box<dyn Write + Send>,
+    input: Box<dyn BufRead + Send>,
+    // innermost call last
+    frames: Vec<Frame>,
+    debugger: Option<Debugger>,
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Evaluator {
-    fn eval_node(node: &Node, env: &mut Environment) -> Result<Object> {
-        match node {
-            Node::Program(program) => Self::eval_program(program, env),
-            Node::Expression(exp) => Self::eval_exp(exp, env),
-            Node::Statement(stmt) => Self::eval_statment(stmt, env),
+    pub fn new() -> Self {
+        Evaluator {
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            call_depth: 0,
+            budget: EvalBudget::default(),
+            steps: 0,
+            allocated_bytes: 0,
+            deadline: None,
+            cancellation: CancellationToken::new(),
+            output: Box::new(io::stdout()),
+            // Without buffering of its own `gets` takes exactly one line from stdin,
+            // leaving the rest to whoever else reads it.
+            input: Box::new(BufReader::with_capacity(1, io::stdin())),
+            frames: Vec::new(),
+            debugger: None,
+        }
+    }
+
+    pub fn with_max_call_depth(mut self, max_call_depth: usize) -> Self {
+        self.max_call_depth = max_call_depth;
+        self
+    }
+
+    pub fn with_budget(mut self, budget: EvalBudget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    pub fn with_cancellation_token(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    /// Where `puts` writes, stdout by default.
+    pub fn with_output(mut self, output: impl Write + Send + 'static) -> Self {
+        self.output = Box::new(output);
+        self
+    }
+
+    /// Where `gets` reads lines from, stdin by default.
+    pub fn with_input(mut self, input: impl BufRead + Send + 'static) -> Self {
+        self.input = Box::new(input);
+        self
+    }
+
+    /// Pauses evaluation in `debugger` before statements and calls, until it is removed.
+    pub fn set_debugger(&mut self, debugger: Option<Debugger>) {
+        self.debugger = debugger;
+    }
+
+    fn step(&mut self) -> Result<()> {
+        self.steps += 1;
+        if let Some(max_steps) = self.budget.max_steps {
+            if self.steps > max_steps {
+                bail!(EvalError::BudgetExceeded(BudgetLimit::Steps(max_steps)));
+            }
+        }
+        if !self.steps.is_multiple_of(POLL_INTERVAL) {
+            return Ok(());
+        }
+        if self.cancellation.is_cancelled() {
+            bail!(EvalError::Interrupted);
+        }
+        if let (Some(deadline), Some(timeout)) = (self.deadline, self.budget.timeout) {
+            if Instant::now() >= deadline {
+                bail!(EvalError::BudgetExceeded(BudgetLimit::Timeout(timeout)));
+            }
+        }
+        Ok(())
+    }
+
+    fn allocate(&mut self, bytes: usize) -> Result<()> {
+        self.allocated_bytes = self.allocated_bytes.saturating_add(bytes);
+        if let Some(max_allocated_bytes) = self.budget.max_allocated_bytes {
+            if self.allocated_bytes > max_allocated_bytes {
+                bail!(EvalError::BudgetExceeded(BudgetLimit::AllocatedBytes(
+                    max_allocated_bytes
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // Charges a new array or hash, or a value made by a native function, to the budget with
+    // everything in it: values read from variables are copies, so none of it is shared.
+    fn allocate_value(&mut self, value: &Object) -> Result<()> {
+        let mut bytes = 0;
+        // with how many arrays, hashes and results each value is nested in
+        let mut pending = vec![(value, 0)];
+        while let Some((value, depth)) = pending.pop() {
+            bytes += mem::size_of::<Object>();
+            let nested = match value {
+                Object::String(s) => {
+                    bytes += s.len();
+                    continue;
+                }
+                Object::Array(elements) => {
+                    pending.extend(elements.iter().map(|element| (element, depth + 1)));
+                    true
+                }
+                Object::Hash(pairs) => {
+                    for (key, value) in pairs {
+                        bytes += mem::size_of::<HashKey>();
+                        if let HashKey::String(s) = key {
+                            bytes += s.len();
+                        }
+                        pending.push((value, depth + 1));
+                    }
+                    true
+                }
+                Object::Ok(value) | Object::Err(value) => {
+                    pending.push((value, depth + 1));
+                    true
+                }
+                _ => false,
+            };
+            if nested && depth >= MAX_NESTING_DEPTH {
+                bail!(EvalError::MaxNestingDepthExceeded {
+                    limit: MAX_NESTING_DEPTH
+                });
+            }
         }
+        self.allocate(bytes)
     }
 
-    fn eval_callable_exp(exp: &CallableExpression, env: &mut Environment) -> Result<Object> {
+    fn eval_callable_exp(
+        &mut self,
+        exp: &CallableExpression,
+        env: &mut Environment,
+    ) -> Result<Object> {
         match exp {
-            CallableExpression::Identifier(ident) => env.get(&ident.value).map_or_else(
-                || Err(anyhow!("identifier not found: {}", ident.value)),
-                |v| Ok(v.clone()),
-            ),
+            CallableExpression::Identifier(ident) => env
+                .get(&ident.value)
+                .or_else(|| Builtin::lookup(&ident.value).map(Object::Builtin))
+                .ok_or_else(|| {
+                    RuntimeError::NameError(format!("identifier not found: {}", ident.value)).into()
+                }),
             //TODO check env clone
-            CallableExpression::FunctionLiteral(func) => Ok(Object::Function(FunctionObj {
-                arguments: func.parameters.clone(),
-                body: func.body.clone(),
-                env: env.clone(),
-            })),
+            CallableExpression::FunctionLiteral(func) => {
+                self.allocate(mem::size_of::<FunctionObj>())?;
+                Ok(Object::Function(FunctionObj {
+                    name: None,
+                    arguments: func.parameters.clone(),
+                    body: func.body.clone(),
+                    env: env.clone(),
+                }))
+            }
         }
     }
 
-    fn eval_exp(exp: &Expression, env: &mut Environment) -> Result<Object> {
+    fn eval_exp(&mut self, exp: &Expression, env: &mut Environment) -> Result<Object> {
+        self.step()?;
         match exp {
-            Expression::Callable(callable) => Self::eval_callable_exp(callable, env),
+            Expression::Callable(callable) => self.eval_callable_exp(callable, env),
             Expression::IntegerLiteral(int) => Ok(Object::Integer(int.value)),
             //TODO clone
-            Expression::StringLiteral(s) => Ok(Object::String(s.value.clone())),
+            Expression::StringLiteral(s) => {
+                self.allocate(s.value.len())?;
+                Ok(Object::String(s.value.clone()))
+            }
             Expression::BooleanLiteral(b) => Ok(Object::Boolean(b.value)),
+            Expression::ArrayLiteral(literal) => {
+                let mut elements = Vec::with_capacity(literal.elements.len());
+                for element in &literal.elements {
+                    elements.push(self.eval_exp(element, env)?);
+                }
+                let array = Object::Array(elements);
+                self.allocate_value(&array)?;
+                Ok(array)
+            }
+            Expression::HashLiteral(literal) => {
+                let mut pairs = BTreeMap::new();
+                for (key, value) in &literal.pairs {
+                    let key = match self.eval_exp(key, env)? {
+                        Object::Integer(n) => HashKey::Integer(n),
+                        Object::Boolean(b) => HashKey::Boolean(b),
+                        Object::String(s) => HashKey::String(s),
+                        key => bail!(RuntimeError::TypeError(format!(
+                            "unusable as hash key: {}",
+                            key.type_val()
+                        ))),
+                    };
+                    pairs.insert(key, self.eval_exp(value, env)?);
+                }
+                let hash = Object::Hash(pairs);
+                self.allocate_value(&hash)?;
+                Ok(hash)
+            }
             Expression::Prefix(exp) => {
-                let right = Self::eval_exp(&exp.right, env)?;
+                let right = self.eval_exp(&exp.right, env)?;
 
                 match exp.operator {
                     Operator::Bang => match right {
@@ -52,54 +466,83 @@ impl Evaluator {
                     },
                     Operator::Minus => {
                         if let Object::Integer(i) = right {
-                            return Ok(Object::Integer(-i));
+                            return match i.checked_neg() {
+                                Some(i) => Ok(Object::Integer(i)),
+                                None => bail!(RuntimeError::ArithmeticError(format!(
+                                    "integer overflow: -{}",
+                                    i
+                                ))),
+                            };
                         }
-                        bail!("unknown operator: -{}", right.type_val(),);
+                        bail!(RuntimeError::TypeError(format!(
+                            "unknown operator: -{}",
+                            right.type_val()
+                        )));
                     }
                     // TODO: check panic ?
                     _ => Ok(Object::Null),
                 }
             }
             Expression::Infix(exp) => {
-                let left_eval = Self::eval_exp(&exp.left, env)?;
-                let right_eval = Self::eval_exp(&exp.right, env)?;
+                let left_eval = self.eval_exp(&exp.left, env)?;
+                let right_eval = self.eval_exp(&exp.right, env)?;
 
                 // test for int operators
                 if let Object::Integer(lval) = left_eval {
                     if let Object::Integer(rval) = right_eval {
                         return match exp.operator {
                             // int result
-                            Operator::Minus => Ok(Object::Integer(lval - rval)),
-                            Operator::Plus => Ok(Object::Integer(lval + rval)),
-                            Operator::Asterisk => Ok(Object::Integer(lval * rval)),
-                            Operator::Slash => Ok(Object::Integer(lval / rval)),
+                            Operator::Slash if rval == 0 => bail!(RuntimeError::ArithmeticError(
+                                "division by zero".to_string()
+                            )),
+                            Operator::Minus
+                            | Operator::Plus
+                            | Operator::Asterisk
+                            | Operator::Slash => {
+                                let value = match exp.operator {
+                                    Operator::Minus => lval.checked_sub(rval),
+                                    Operator::Plus => lval.checked_add(rval),
+                                    Operator::Asterisk => lval.checked_mul(rval),
+                                    _ => lval.checked_div(rval),
+                                };
+                                match value {
+                                    Some(value) => Ok(Object::Integer(value)),
+                                    None => bail!(RuntimeError::ArithmeticError(format!(
+                                        "integer overflow: {} {} {}",
+                                        lval, exp.operator, rval
+                                    ))),
+                                }
+                            }
                             Operator::Eq => Ok(Object::Boolean(lval == rval)),
                             Operator::NotEq => Ok(Object::Boolean(lval != rval)),
                             Operator::Lt => Ok(Object::Boolean(lval < rval)),
                             Operator::Gt => Ok(Object::Boolean(lval > rval)),
                             //TODO panic is it a bug ?
-                            _ => bail!(
+                            _ => bail!(RuntimeError::TypeError(format!(
                                 "unknown operator: {} {} {}",
                                 left_eval.type_val(),
                                 exp.operator,
                                 right_eval.type_val()
-                            ),
+                            ))),
                         };
                     }
                 }
-                
+
                 // test for string operators
                 if let Object::String(lval) = &left_eval {
                     if let Object::String(rval) = &right_eval {
                         return match exp.operator {
                             // int result
-                            Operator::Plus => Ok(Object::String(format!("{}{}", &lval, &rval))),
-                            _ => bail!(
+                            Operator::Plus => {
+                                self.allocate(lval.len() + rval.len())?;
+                                Ok(Object::String(format!("{}{}", &lval, &rval)))
+                            }
+                            _ => bail!(RuntimeError::TypeError(format!(
                                 "unknown operator: {} {} {}",
                                 left_eval.type_val(),
                                 exp.operator,
                                 right_eval.type_val()
-                            ),
+                            ))),
                         };
                     }
                 }
@@ -110,80 +553,563 @@ impl Evaluator {
                     Operator::NotEq => Ok(Object::Boolean(left_eval != right_eval)),
                     op => {
                         if left_eval.type_val() == right_eval.type_val() {
-                            bail!(
+                            bail!(RuntimeError::TypeError(format!(
                                 "unknown operator: {} {} {}",
                                 left_eval.type_val(),
                                 op,
                                 right_eval.type_val()
-                            )
+                            )))
                         }
-                        bail!(
+                        bail!(RuntimeError::TypeError(format!(
                             "type mismatch: {} {} {}",
                             left_eval.type_val(),
                             op,
                             right_eval.type_val()
-                        )
+                        )))
+                    }
+                }
+            }
+            Expression::Try(exp) => self.eval_try(exp, env),
+            Expression::Match(exp) => match self.match_arm(exp, env)? {
+                (ArmBody::Expression(body), mut scope) => self.eval_exp(body, &mut scope),
+                (ArmBody::Block(body), mut scope) => {
+                    let obj = self.eval_block_statments(body, &mut scope)?;
+                    self.apply_returned_call(obj)
+                }
+            },
+            Expression::Propagate(exp) => match self.eval_exp(&exp.value, env)? {
+                Object::Ok(value) => Ok(*value),
+                error @ Object::Err(_) => Err(anyhow::Error::new(EarlyReturn(error))),
+                obj => bail!(RuntimeError::TypeError(format!(
+                    "operand of ? must be OK or ERR, got {}",
+                    obj.type_val()
+                ))),
+            },
+            Expression::If(exp) => {
+                let obj = self.eval_if(exp, env)?;
+                self.apply_returned_call(obj)
+            }
+            Expression::Call(call) => {
+                let (func, args) = self.eval_call(call, env)?;
+                match func {
+                    Object::Function(func) => {
+                        let frame = Self::frame(&func, call);
+                        self.before_call(&frame, env)?;
+                        self.apply_nested(func, args, frame)
+                    }
+                    func => self.apply_native(func, &args),
+                }
+            }
+        }
+    }
+
+    // Evaluates an expression whose value is the result of the enclosing function: calls
+    // are not applied but returned as `Flow::TailCall` for the trampoline.
+    fn eval_tail_exp(&mut self, exp: &Expression, env: &mut Environment) -> Result<Object> {
+        self.step()?;
+        match exp {
+            Expression::Call(call) => {
+                let (func, arguments) = self.eval_call(call, env)?;
+                match func {
+                    Object::Function(func) => {
+                        let frame = Self::frame(&func, call);
+                        self.before_call(&frame, env)?;
+                        Ok(Object::tail_call(TailCallObj {
+                            func,
+                            arguments,
+                            frame,
+                        }))
                     }
+                    func => self.apply_native(func, &arguments),
                 }
             }
             Expression::If(exp) => {
-                let condition = Self::eval_exp(&exp.condition, env)?;
+                let condition = self.eval_exp(&exp.condition, env)?;
                 if condition.is_thruthy() {
-                    Self::eval_block_statments(&exp.consequence, env)
+                    self.eval_tail_block(&exp.consequence, env)
                 } else if let Some(alternative) = &exp.alternative {
-                    Self::eval_block_statments(alternative, env)
+                    self.eval_tail_block(alternative, env)
                 } else {
                     Ok(Object::Null)
                 }
             }
-            Expression::Call(call) => {
-                let call_exp = Self::eval_callable_exp(&call.func, env)?;
-                if let Object::Function(func) = call_exp {
-                    let args: Result<Vec<_>, _> = call
-                        .arguments
-                        .iter()
-                        .map(|arg| Self::eval_exp(arg, env))
-                        .collect();
-                    let mut ext_env = Environment::new_with_outer(&func.env);
-                    let args = args?;
-
-                    // TODO 2 many clones
-                    for (idx, param) in func.arguments.iter().enumerate() {
-                        if let Some(arg) = args.get(idx) {
-                            ext_env.set(param.value.clone(), arg.clone());
-                        }
+            Expression::Match(exp) => match self.match_arm(exp, env)? {
+                (ArmBody::Expression(body), mut scope) => self.eval_tail_exp(body, &mut scope),
+                (ArmBody::Block(body), mut scope) => self.eval_tail_block(body, &mut scope),
+            },
+            exp => self.eval_exp(exp, env),
+        }
+    }
+
+    fn eval_if(&mut self, exp: &IfExpression, env: &mut Environment) -> Result<Object> {
+        let condition = self.eval_exp(&exp.condition, env)?;
+        if condition.is_thruthy() {
+            self.eval_block_statments(&exp.consequence, env)
+        } else if let Some(alternative) = &exp.alternative {
+            self.eval_block_statments(alternative, env)
+        } else {
+            Ok(Object::Null)
+        }
+    }
+
+    // A `return` inside an `if` whose value is used, by a `let` or an operator, is not in
+    // tail position: a call it returns is applied here rather than by the trampoline.
+    fn apply_returned_call(&mut self, obj: Object) -> Result<Object> {
+        match obj {
+            Object::ControlFlow(ControlFlow(Flow::Return(obj))) => match *obj {
+                Object::ControlFlow(ControlFlow(Flow::TailCall(call))) => {
+                    let obj = self.apply_nested(call.func, call.arguments, call.frame)?;
+                    Ok(Object::return_value(obj))
+                }
+                obj => Ok(Object::return_value(obj)),
+            },
+            obj => Ok(obj),
+        }
+    }
+
+    fn eval_call(
+        &mut self,
+        call: &CallExpression,
+        env: &mut Environment,
+    ) -> Result<(Object, Vec<Object>)> {
+        let func = self.eval_callable_exp(&call.func, env)?;
+        let args: Result<Vec<_>, _> = call
+            .arguments
+            .iter()
+            .map(|arg| self.eval_exp(arg, env))
+            .collect();
+        Ok((func, args?))
+    }
+
+    fn frame(func: &FunctionObj, call: &CallExpression) -> Frame {
+        let function = func.name.clone().unwrap_or_else(|| match &call.func {
+            CallableExpression::Identifier(ident) => ident.value.clone(),
+            CallableExpression::FunctionLiteral(_) => "anonymous function".to_string(),
+        });
+        Frame {
+            function,
+            call_site: Some(call.span),
+            line: call.line,
+        }
+    }
+
+    // Functions implemented in Rust never evaluate Monkey code, so they need neither the
+    // trampoline nor call depth accounting.
+    fn apply_native(&mut self, func: Object, args: &[Object]) -> Result<Object> {
+        let obj = match func {
+            Object::HostFunction(func) => func.call(args)?,
+            Object::Builtin(builtin) => builtin.apply(args, &mut self.output, &mut self.input)?,
+            obj => bail!(RuntimeError::TypeError(format!(
+                "not a function: {}",
+                obj.type_val()
+            ))),
+        };
+        self.allocate_value(&obj)?;
+        Ok(obj)
+    }
+
+    // A call that is not in tail position, so runs on a Rust frame of its own.
+    fn apply_nested(
+        &mut self,
+        func: FunctionObj,
+        args: Vec<Object>,
+        frame: Frame,
+    ) -> Result<Object> {
+        if self.call_depth >= self.max_call_depth {
+            bail!(EvalError::MaxCallDepthExceeded {
+                function: frame.function,
+                limit: self.max_call_depth,
+            });
+        }
+        self.call_depth += 1;
+        let obj = stacker::maybe_grow(STACK_RED_ZONE_BYTES, STACK_SEGMENT_BYTES, || {
+            self.apply_function(func, args, frame)
+        });
+        self.call_depth -= 1;
+        obj
+    }
+
+    fn apply_function(
+        &mut self,
+        func: FunctionObj,
+        args: Vec<Object>,
+        frame: Frame,
+    ) -> Result<Object> {
+        self.frames.push(frame);
+        let obj = self
+            .run_function(func, args)
+            .map_err(|err| self.traced(err));
+        self.frames.pop();
+        obj
+    }
+
+    // Turns the unwinding of `?` back into the `return` it stands for, once it reaches a
+    // function body, the top level or a `try` block.
+    fn early_return(err: anyhow::Error) -> Result<Object> {
+        match err.downcast::<EarlyReturn>() {
+            Ok(EarlyReturn(obj)) => Ok(Object::return_value(obj)),
+            Err(err) => Err(err),
+        }
+    }
+
+    // Running out of budget or being interrupted is no fault of the code that was running,
+    // so neither is traced nor caught.
+    fn is_stop(err: &anyhow::Error) -> bool {
+        matches!(
+            err.downcast_ref::<EvalError>(),
+            Some(EvalError::BudgetExceeded(_) | EvalError::Interrupted)
+        )
+    }
+
+    // Attaches the calls in progress to an error raised by the innermost one.
+    fn traced(&self, err: anyhow::Error) -> anyhow::Error {
+        if Self::is_stop(&err) || err.downcast_ref::<Traceback>().is_some() {
+            return err;
+        }
+        let traceback = Traceback {
+            message: err.to_string(),
+            frames: self.frames.clone(),
+        };
+        err.context(traceback)
+    }
+
+    // The trampoline: tail calls replace the frame of the function making them.
+    fn run_function(&mut self, mut func: FunctionObj, mut args: Vec<Object>) -> Result<Object> {
+        loop {
+            self.allocate(
+                mem::size_of::<Environment>()
+                    + args.len() * (mem::size_of::<String>() + mem::size_of::<Object>()),
+            )?;
+            let mut ext_env = Environment::new_with_outer(&func.env);
+
+            // TODO 2 many clones
+            for (param, arg) in func.arguments.iter().zip(args) {
+                ext_env.set(param.value.clone(), arg);
+            }
+
+            let obj = match self
+                .eval_tail_block(&func.body, &mut ext_env)
+                .or_else(Self::early_return)?
+            {
+                Object::ControlFlow(ControlFlow(Flow::Return(obj))) => *obj,
+                obj => obj,
+            };
+            match obj {
+                Object::ControlFlow(ControlFlow(Flow::TailCall(call))) => {
+                    if let Some(frame) = self.frames.last_mut() {
+                        *frame = call.frame;
                     }
+                    func = call.func;
+                    args = call.arguments;
+                }
+                obj => return Ok(obj),
+            }
+        }
+    }
+
+    // Finds the first arm that matches the value and whose guard holds, returning its body
+    // and the scope of the names it binds, which the body is evaluated in.
+    fn match_arm<'a>(
+        &mut self,
+        exp: &'a MatchExpression,
+        env: &mut Environment,
+    ) -> Result<(&'a ArmBody, Environment)> {
+        let value = self.eval_exp(&exp.value, env)?;
+        for arm in &exp.arms {
+            let mut bindings = Vec::new();
+            if self
+                .destructure(&arm.pattern, &value, &mut bindings, env)?
+                .is_err()
+            {
+                continue;
+            }
+            let mut scope = Environment::new_with_outer(env);
+            for (name, value) in bindings {
+                scope.set(name, value);
+            }
+            let guard = match &arm.guard {
+                Some(guard) => self.eval_exp(guard, &mut scope)?.is_thruthy(),
+                None => true,
+            };
+            if guard {
+                return Ok((&arm.body, scope));
+            }
+        }
+        bail!("no match arm for {}", value.inspect());
+    }
 
-                    return Self::eval_block_statments(&func.body, &mut ext_env);
+    // Collects what `pattern` binds in `value`. A value without the shape of the pattern
+    // is not an error of the evaluation but the inner `Err`, so that a match arm is only
+    // skipped when its own pattern does not match.
+    fn destructure(
+        &mut self,
+        pattern: &Pattern,
+        value: &Object,
+        bindings: &mut Vec<(String, Object)>,
+        env: &mut Environment,
+    ) -> Result<Result<(), RuntimeError>> {
+        let mismatch = |message: String| Ok(Err(RuntimeError::TypeError(message)));
+        match (pattern, value) {
+            (Pattern::Wildcard(_), _) => {}
+            (Pattern::Binding(ident), value) => {
+                bindings.push((ident.value.clone(), value.clone()));
+            }
+            (Pattern::Literal(literal), value) => {
+                if self.eval_exp(literal, env)? != *value {
+                    return mismatch(format!(
+                        "cannot destructure {} as {}",
+                        value.inspect(),
+                        literal
+                    ));
+                }
+            }
+            (Pattern::Default(pattern), value) => {
+                return self.destructure(&pattern.pattern, value, bindings, env)
+            }
+            (Pattern::Array(pattern), Object::Array(elements)) => {
+                let count = pattern.elements.len();
+                if pattern.rest.is_none() && elements.len() > count {
+                    return mismatch(format!(
+                        "cannot destructure an array of length {} into {} elements",
+                        elements.len(),
+                        count
+                    ));
+                }
+                for (idx, element_pattern) in pattern.elements.iter().enumerate() {
+                    let matched = match (elements.get(idx), element_pattern) {
+                        (Some(element), _) => {
+                            self.destructure(element_pattern, element, bindings, env)?
+                        }
+                        (None, Pattern::Default(default)) => {
+                            self.destructure_default(default, bindings, env)?
+                        }
+                        (None, _) => {
+                            return mismatch(format!(
+                                "cannot destructure an array of length {} into {}{} elements",
+                                elements.len(),
+                                if pattern.rest.is_some() {
+                                    "at least "
+                                } else {
+                                    ""
+                                },
+                                count
+                            ))
+                        }
+                    };
+                    if matched.is_err() {
+                        return Ok(matched);
+                    }
+                }
+                if let Some(rest) = pattern.rest.as_ref().filter(|rest| rest.value != "_") {
+                    let rest_elements = elements.get(count..).unwrap_or_default().to_vec();
+                    bindings.push((rest.value.clone(), Object::Array(rest_elements)));
+                }
+            }
+            (Pattern::Hash(pattern), Object::Hash(pairs)) => {
+                for field in &pattern.fields {
+                    let key = HashKey::String(field.key.value.clone());
+                    let matched = match (pairs.get(&key), &field.pattern) {
+                        (Some(value), pattern) => {
+                            self.destructure(pattern, value, bindings, env)?
+                        }
+                        (None, Pattern::Default(default)) => {
+                            self.destructure_default(default, bindings, env)?
+                        }
+                        (None, _) => {
+                            return mismatch(format!(
+                                "cannot destructure a hash without the key `{}`",
+                                field.key
+                            ))
+                        }
+                    };
+                    if matched.is_err() {
+                        return Ok(matched);
+                    }
                 }
-                panic!("callable is not a function");
             }
+            (Pattern::Array(_), value) => {
+                return mismatch(format!(
+                    "cannot destructure {} as an array",
+                    value.type_val()
+                ))
+            }
+            (Pattern::Hash(_), value) => {
+                return mismatch(format!("cannot destructure {} as a hash", value.type_val()))
+            }
+        }
+        Ok(Ok(()))
+    }
+
+    // Destructures the default of a missing element or key, which can refer to the names
+    // bound before it by the same pattern.
+    fn destructure_default(
+        &mut self,
+        default: &DefaultPattern,
+        bindings: &mut Vec<(String, Object)>,
+        env: &mut Environment,
+    ) -> Result<Result<(), RuntimeError>> {
+        let mut scope = Environment::new_with_outer(env);
+        for (name, value) in bindings.iter() {
+            scope.set(name.clone(), value.clone());
+        }
+        let value = self.eval_exp(&default.default, &mut scope)?;
+        self.destructure(&default.pattern, &value, bindings, env)
+    }
+
+    fn eval_try(&mut self, exp: &TryExpression, env: &mut Environment) -> Result<Object> {
+        let mut result = self.eval_guarded(&exp.body, env);
+        if let (Err(err), Some(catch)) = (&result, &exp.catch) {
+            if !Self::is_stop(err) {
+                // the error is only bound inside the `catch` body
+                let error = self.caught(result.unwrap_err());
+                let mut scope = Environment::new_with_outer(env);
+                scope.set(catch.name.value.clone(), Object::Error(error));
+                result = self.eval_guarded(&catch.body, &mut scope);
+            }
+        }
+
+        let Some(finally) = &exp.finally else {
+            return result;
+        };
+        if matches!(&result, Err(err) if Self::is_stop(err)) {
+            return result;
+        }
+        // its value is dropped, unless it returns or fails
+        match self.eval_guarded(finally, env)? {
+            obj @ Object::ControlFlow(ControlFlow(Flow::Return(_))) => Ok(obj),
+            _ => result,
+        }
+    }
+
+    // Evaluates a block of a `try`, applying a tail call it returns right away so that
+    // its errors are raised inside.
+    fn eval_guarded(&mut self, block: &BlockStatement, env: &mut Environment) -> Result<Object> {
+        if block.statements.is_empty() {
+            return Ok(Object::Null);
+        }
+        let obj = self
+            .eval_block_statments(block, env)
+            .or_else(Self::early_return)?;
+        self.apply_returned_call(obj)
+    }
+
+    // The value `catch` binds for an error.
+    fn caught(&self, err: anyhow::Error) -> ErrorObj {
+        if let Some(error) = err.downcast_ref::<ErrorObj>() {
+            return error.clone();
+        }
+        let (message, trace) = match err.downcast_ref::<Traceback>() {
+            Some(traceback) => (traceback.message.clone(), traceback.frames.clone()),
+            None => (err.to_string(), self.frames.clone()),
+        };
+        let kind = if let Some(error) = err.downcast_ref::<RuntimeError>() {
+            error.kind()
+        } else if let Some(EvalError::MaxCallDepthExceeded { .. }) = err.downcast_ref() {
+            "RecursionError"
+        } else {
+            "RuntimeError"
+        };
+        ErrorObj {
+            kind: kind.to_string(),
+            message,
+            trace,
         }
     }
 
-    fn eval_statment(stmt: &Statement, env: &mut Environment) -> Result<Object> {
+    // Raises `error` with the trace of where it was first raised.
+    fn throw(error: ErrorObj) -> anyhow::Error {
+        let traceback = Traceback {
+            message: error.message.clone(),
+            frames: error.trace.clone(),
+        };
+        let err = anyhow::Error::new(error);
+        if traceback.frames.is_empty() {
+            return err;
+        }
+        err.context(traceback)
+    }
+
+    fn before_statement(&mut self, stmt: &Statement, env: &Environment) -> Result<()> {
+        match &mut self.debugger {
+            Some(debugger) => debugger.before_statement(stmt.span(), env, &self.frames),
+            None => Ok(()),
+        }
+    }
+
+    fn before_call(&mut self, frame: &Frame, env: &Environment) -> Result<()> {
+        match &mut self.debugger {
+            Some(debugger) => debugger.before_call(frame, env, &self.frames),
+            None => Ok(()),
+        }
+    }
+
+    fn eval_statment(&mut self, stmt: &Statement, env: &mut Environment) -> Result<Object> {
+        self.step()?;
+        self.before_statement(stmt, env)?;
         match stmt {
-            Statement::Expression(exp) => Self::eval_exp(&exp.expression, env),
-            Statement::Return(r) => {
-                let val = Self::eval_exp(&r.value, env)?;
-                Ok(Object::ReturnValue(Box::new(val)))
+            // the value of an `if` statement is dropped, so calls it returns stay in tail
+            // position
+            Statement::Expression(exp) => match &exp.expression {
+                Expression::If(exp) => {
+                    self.step()?;
+                    self.eval_if(exp, env)
+                }
+                exp => self.eval_exp(exp, env),
+            },
+            Statement::Return(r) => match self.eval_tail_exp(&r.value, env)? {
+                obj @ Object::ControlFlow(ControlFlow(Flow::Return(_))) => Ok(obj),
+                obj => Ok(Object::return_value(obj)),
+            },
+            Statement::Throw(t) => {
+                let error = match self.eval_exp(&t.value, env)? {
+                    Object::Error(error) => error,
+                    value => ErrorObj {
+                        kind: "Error".to_string(),
+                        message: value.to_string(),
+                        trace: self.frames.clone(),
+                    },
+                };
+                Err(Self::throw(error))
             }
-            Statement::Block(block) => Self::eval_block_statments(block, env),
+            Statement::Block(block) => self.eval_block_statments(block, env),
             Statement::Let(l) => {
-                let val = Self::eval_exp(&l.value, env)?;
-                env.set(l.name.value.clone(), val);
+                let val = self.eval_exp(&l.value, env)?;
+                if let Some(name) = l.name() {
+                    let val = match val {
+                        Object::Function(mut func) if func.name.is_none() => {
+                            func.name = Some(name.value.clone());
+                            Object::Function(func)
+                        }
+                        val => val,
+                    };
+                    env.set(name.value.clone(), val);
+                    return Ok(Object::Null);
+                }
+
+                let mut bindings = Vec::new();
+                if let Err(mismatch) = self.destructure(&l.pattern, &val, &mut bindings, env)? {
+                    bail!(mismatch);
+                }
+                for (name, value) in bindings {
+                    env.set(name, value);
+                }
                 Ok(Object::Null)
             }
         }
     }
 
-    fn eval_program(program: &Program, env: &mut Environment) -> Result<Object> {
+    fn eval_program(&mut self, program: &Program, env: &mut Environment) -> Result<Object> {
         let mut obj = None;
 
         for stmt in &program.statments {
-            obj = Some(Self::eval_statment(stmt, env)?);
-            if let Some(Object::ReturnValue(r)) = obj {
-                return Ok(*r);
+            obj = Some(self.eval_statment(stmt, env).or_else(Self::early_return)?);
+            if let Some(Object::ControlFlow(ControlFlow(Flow::Return(r)))) = obj {
+                return match *r {
+                    Object::ControlFlow(ControlFlow(Flow::TailCall(call))) => {
+                        self.apply_function(call.func, call.arguments, call.frame)
+                    }
+                    obj => Ok(obj),
+                };
             }
         }
 
@@ -193,12 +1119,16 @@ impl Evaluator {
         bail!("empty statments");
     }
 
-    fn eval_block_statments(block: &BlockStatement, env: &mut Environment) -> Result<Object> {
+    fn eval_block_statments(
+        &mut self,
+        block: &BlockStatement,
+        env: &mut Environment,
+    ) -> Result<Object> {
         let mut obj = None;
 
         for stmt in &block.statements {
-            obj = Some(Self::eval_statment(stmt, env)?);
-            if let Some(Object::ReturnValue(_)) = obj {
+            obj = Some(self.eval_statment(stmt, env)?);
+            if let Some(Object::ControlFlow(ControlFlow(Flow::Return(_)))) = obj {
                 return Ok(obj.unwrap());
             }
         }
@@ -209,8 +1139,56 @@ impl Evaluator {
         bail!("empty statments");
     }
 
-    pub fn eval(program: Program, env: &mut Environment) -> Result<Object> {
-        Self::eval_node(&Node::Program(program), env)
+    fn eval_tail_block(&mut self, block: &BlockStatement, env: &mut Environment) -> Result<Object> {
+        let Some((last, statements)) = block.statements.split_last() else {
+            bail!("empty statments");
+        };
+
+        for stmt in statements {
+            let obj = self.eval_statment(stmt, env)?;
+            if let Object::ControlFlow(ControlFlow(Flow::Return(_))) = obj {
+                return Ok(obj);
+            }
+        }
+
+        match last {
+            Statement::Expression(exp) => {
+                self.before_statement(last, env)?;
+                self.eval_tail_exp(&exp.expression, env)
+            }
+            stmt => self.eval_statment(stmt, env),
+        }
+    }
+
+    fn start_run(&mut self) {
+        // a run that panicked never unwound its calls
+        self.call_depth = 0;
+        self.frames.clear();
+        self.steps = 0;
+        self.allocated_bytes = 0;
+        self.deadline = self.budget.timeout.map(|timeout| Instant::now() + timeout);
+    }
+
+    pub fn eval(&mut self, program: Program, env: &mut Environment) -> Result<Object> {
+        self.start_run();
+        self.eval_program(&program, env)
+    }
+
+    /// Applies a function value to already evaluated arguments, as a run of its own for
+    /// the budget.
+    pub fn call(&mut self, name: &str, func: Object, args: Vec<Object>) -> Result<Object> {
+        self.start_run();
+        match func {
+            Object::Function(func) => {
+                let frame = Frame {
+                    function: name.to_string(),
+                    call_site: None,
+                    line: 0,
+                };
+                self.apply_function(func, args, frame)
+            }
+            func => self.apply_native(func, &args),
+        }
     }
 }
 
@@ -218,9 +1196,19 @@ impl Evaluator {
 mod tests {
     use anyhow::Result;
 
-    use crate::{environment::Environment, lexer::Lexer, object::Object, parser::Parser};
+    use crate::{
+        environment::Environment,
+        lexer::Lexer,
+        object::{HashKey, Object},
+        parser::Parser,
+        shared_io::SharedWriter,
+    };
+
+    use std::{collections::BTreeMap, io::Cursor, thread, time::Duration};
 
-    use super::Evaluator;
+    use super::{
+        BudgetLimit, CancellationToken, EvalBudget, EvalError, Evaluator, MAX_NESTING_DEPTH,
+    };
 
     struct ObjectTest<'a> {
         pub input: &'a str,
@@ -238,18 +1226,16 @@ mod tests {
         let program = p.parse_program();
         let mut test_env = Environment::default();
 
-        Evaluator::eval(program, &mut test_env)
+        Evaluator::default().eval(program, &mut test_env)
     }
 
     #[test]
     fn test_eval_string_expression() {
-        let tests = vec![
-            ObjectTest {
-                input: "\"hello world\";",
-                expected: Object::String("hello world".to_string()),
-            },
-        ];
-        
+        let tests = vec![ObjectTest {
+            input: "\"hello world\";",
+            expected: Object::String("hello world".to_string()),
+        }];
+
         for test in tests {
             let obj = test_eval(test.input).unwrap();
             assert_eq!(
@@ -261,16 +1247,44 @@ mod tests {
     }
 
     #[test]
-    fn test_eval_string_concat() {
+    fn test_array_and_hash_literals() {
+        let int = |n| Object::Integer(n);
         let tests = vec![
-            ObjectTest {
-                input: "\"hello\" + \" \" + \"world\";",
-                expected: Object::String("hello world".to_string()),
-            },
+            (
+                "[1, 2 * 3, []]",
+                Object::Array(vec![int(1), int(6), Object::Array(vec![])]),
+            ),
+            (
+                "let k = \"b\"; {\"a\": 1, k: 2, 3: true, true: 4}",
+                Object::Hash(BTreeMap::from([
+                    (HashKey::String("a".to_string()), int(1)),
+                    (HashKey::String("b".to_string()), int(2)),
+                    (HashKey::Integer(3), Object::Boolean(true)),
+                    (HashKey::Boolean(true), int(4)),
+                ])),
+            ),
+            (
+                "{1: 1, 1: 2}",
+                Object::Hash(BTreeMap::from([(HashKey::Integer(1), int(2))])),
+            ),
         ];
-        
-        for test in tests {
-            let obj = test_eval(test.input).unwrap();
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input).unwrap(), expected, "{}", input);
+        }
+
+        let err = test_eval("{[1]: 1}").unwrap_err();
+        assert_eq!(err.to_string(), "unusable as hash key: ARRAY");
+    }
+
+    #[test]
+    fn test_eval_string_concat() {
+        let tests = vec![ObjectTest {
+            input: "\"hello\" + \" \" + \"world\";",
+            expected: Object::String("hello world".to_string()),
+        }];
+
+        for test in tests {
+            let obj = test_eval(test.input).unwrap();
             assert_eq!(
                 obj, test.expected,
                 "object doesnt match expected: {:?}, {:?}",
@@ -643,4 +1657,793 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_tail_calls() {
+        let tests = vec![
+            ObjectTest {
+                input: "let countdown = fn(n) { if (n == 0) { 0 } else { countdown(n - 1) } }; countdown(50000);",
+                expected: Object::Integer(0),
+            },
+            ObjectTest {
+                input: "let sum = fn(n, acc) { if (n == 0) { return acc; } return sum(n - 1, acc + n); }; sum(50000, 0);",
+                expected: Object::Integer(1250025000),
+            },
+            ObjectTest {
+                input: "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } }; let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } }; even(50001);",
+                expected: Object::Boolean(false),
+            },
+            ObjectTest {
+                input: "let fact = fn(n) { if (n == 0) { 1 } else { n * fact(n - 1) } }; fact(10);",
+                expected: Object::Integer(3628800),
+            },
+            ObjectTest {
+                input: "let one = fn() { return 1; }; let two = fn() { one(); 2; }; two();",
+                expected: Object::Integer(2),
+            },
+            ObjectTest {
+                input: "let down = fn(n) { if (n > 0) { return down(n - 1); } n }; down(50000);",
+                expected: Object::Integer(0),
+            },
+        ];
+
+        for test in tests {
+            let obj = test_eval(test.input).unwrap();
+            assert_eq!(
+                obj, test.expected,
+                "object doesnt match expected: {:?}, {:?}",
+                obj, test.expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_return_outside_tail_position() {
+        let output = SharedWriter::new(Vec::new());
+        let mut evaluator = Evaluator::new().with_output(output.clone());
+        let input = "let g = fn() { 1 }; let f = fn() { let x = if (true) { return g(); }; puts(x); 7 }; f();";
+        let program = Parser::new(Lexer::new(input)).parse_program();
+
+        let obj = evaluator
+            .eval(program, &mut Environment::default())
+            .unwrap();
+        assert_eq!(obj, Object::Integer(7));
+        assert_eq!(String::from_utf8(output.lock().clone()).unwrap(), "1\n");
+    }
+
+    #[test]
+    fn test_long_closure_chains() {
+        // every closure keeps the scope of the call before alive, all of them are dropped
+        // when the last one is
+        let input = "let f = fn(n, acc) { if (n == 0) { 0 } else { f(n - 1, fn() { acc }) } }; f(200000, 0);";
+        assert_eq!(test_eval(input).unwrap(), Object::Integer(0));
+    }
+
+    #[test]
+    fn test_max_call_depth() {
+        let input = "let deep = fn(n) { if (n == 0) { 0 } else { 1 + deep(n - 1) } };";
+        let mut evaluator = Evaluator::new().with_max_call_depth(50);
+        let mut env = Environment::default();
+        evaluator
+            .eval(Parser::new(Lexer::new(input)).parse_program(), &mut env)
+            .unwrap();
+
+        let obj = evaluator
+            .eval(
+                Parser::new(Lexer::new("deep(49);")).parse_program(),
+                &mut env,
+            )
+            .unwrap();
+        assert_eq!(obj, Object::Integer(49));
+
+        let err = evaluator
+            .eval(
+                Parser::new(Lexer::new("deep(50);")).parse_program(),
+                &mut env,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<EvalError>(),
+            Some(&EvalError::MaxCallDepthExceeded {
+                function: "deep".to_string(),
+                limit: 50
+            })
+        );
+        assert_eq!(
+            err.to_string(),
+            "maximum call depth exceeded: 50 calls deep in deep\n  \
+             in deep, called at line 1 (48 more times)\n  \
+             in deep, called at line 1"
+        );
+
+        // tail calls reuse the frame and the evaluator is usable after the error
+        let input = "let countdown = fn(n) { if (n == 0) { 0 } else { countdown(n - 1) } }; countdown(1000);";
+        let obj = evaluator
+            .eval(Parser::new(Lexer::new(input)).parse_program(), &mut env)
+            .unwrap();
+        assert_eq!(obj, Object::Integer(0));
+    }
+
+    fn test_eval_budget(input: &str, budget: EvalBudget) -> Result<Object> {
+        let program = Parser::new(Lexer::new(input)).parse_program();
+        let mut env = Environment::default();
+        Evaluator::new().with_budget(budget).eval(program, &mut env)
+    }
+
+    #[test]
+    fn test_eval_budget_exceeded() {
+        let forever = "let forever = fn(n) { forever(n + 1) }; forever(0);";
+
+        let err = test_eval_budget(
+            "let spin = fn() { spin() }; spin();",
+            EvalBudget {
+                max_steps: Some(1000),
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<EvalError>(),
+            Some(&EvalError::BudgetExceeded(BudgetLimit::Steps(1000)))
+        );
+
+        let err = test_eval_budget(
+            forever,
+            EvalBudget {
+                max_steps: Some(1000),
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<EvalError>(),
+            Some(&EvalError::BudgetExceeded(BudgetLimit::Steps(1000)))
+        );
+
+        let timeout = Duration::from_millis(20);
+        let err = test_eval_budget(
+            forever,
+            EvalBudget {
+                timeout: Some(timeout),
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<EvalError>(),
+            Some(&EvalError::BudgetExceeded(BudgetLimit::Timeout(timeout)))
+        );
+
+        let grow = "let grow = fn(s, n) { if (n == 0) { s } else { grow(s + s, n - 1) } }; grow(\"ab\", 64);";
+        let err = test_eval_budget(
+            grow,
+            EvalBudget {
+                max_allocated_bytes: Some(1 << 20),
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<EvalError>(),
+            Some(&EvalError::BudgetExceeded(BudgetLimit::AllocatedBytes(
+                1 << 20
+            )))
+        );
+        assert_eq!(
+            err.to_string(),
+            "evaluation budget exceeded: more than 1048576 bytes allocated"
+        );
+
+        let grow = "let grow = fn(a, n) { if (n == 0) { a } else { grow([a, a, a, a], n - 1) } }; grow(1, 11);";
+        let err = test_eval_budget(
+            grow,
+            EvalBudget {
+                max_steps: Some(10_000),
+                max_allocated_bytes: Some(64 << 10),
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<EvalError>(),
+            Some(&EvalError::BudgetExceeded(BudgetLimit::AllocatedBytes(
+                64 << 10
+            )))
+        );
+    }
+
+    #[test]
+    fn test_max_nesting_depth() {
+        let nest = |wrap: &str, depth: usize| {
+            format!(
+                "let nest = fn(n, acc) {{ if (n == 0) {{ acc }} else {{ nest(n - 1, {}) }} }}; let deep = nest({}, 0); [deep] == [deep];",
+                wrap, depth
+            )
+        };
+
+        for wrap in ["[acc]", "{\"a\": acc}", "ok(acc)"] {
+            // copied, compared and dropped on the 2 MiB stack of a test thread
+            let obj = test_eval(&nest(wrap, MAX_NESTING_DEPTH - 1)).unwrap();
+            assert_eq!(obj, Object::Boolean(true), "{}", wrap);
+
+            let err = test_eval(&nest(wrap, MAX_NESTING_DEPTH + 1)).unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<EvalError>(),
+                Some(&EvalError::MaxNestingDepthExceeded {
+                    limit: MAX_NESTING_DEPTH
+                }),
+                "{}",
+                wrap
+            );
+        }
+
+        let obj = test_eval(&format!("{} deep", nest("[acc]", MAX_NESTING_DEPTH - 1))).unwrap();
+        let printed = obj.to_string();
+        assert!(printed.starts_with("[[[") && printed.ends_with("]]]"));
+    }
+
+    #[test]
+    fn test_eval_within_budget() {
+        let budget = EvalBudget {
+            max_steps: Some(10_000),
+            max_allocated_bytes: Some(1 << 20),
+            timeout: Some(Duration::from_secs(10)),
+        };
+        let input = "let add = fn(x, y) { x + y; }; add(5, add(5,5));";
+        assert_eq!(
+            test_eval_budget(input, budget).unwrap(),
+            Object::Integer(15)
+        );
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let forever = "let forever = fn(n) { forever(n + 1) }; forever(0);";
+        let cancellation = CancellationToken::new();
+        let mut evaluator = Evaluator::new().with_cancellation_token(cancellation.clone());
+
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            cancellation.cancel();
+        });
+        let program = Parser::new(Lexer::new(forever)).parse_program();
+        let err = evaluator
+            .eval(program, &mut Environment::default())
+            .unwrap_err();
+        canceller.join().unwrap();
+
+        assert_eq!(
+            err.downcast_ref::<EvalError>(),
+            Some(&EvalError::Interrupted)
+        );
+        assert_eq!(err.to_string(), "evaluation interrupted");
+    }
+
+    #[test]
+    fn test_try_catch() {
+        let tests = vec![
+            ObjectTest {
+                input: "try { throw 1; 2 } catch (e) { error_message(e) }",
+                expected: Object::String("1".to_string()),
+            },
+            ObjectTest {
+                input: "try { 1 + true } catch (e) { error_kind(e) }",
+                expected: Object::String("TypeError".to_string()),
+            },
+            ObjectTest {
+                input: "try { missing } catch (e) { error_kind(e) }",
+                expected: Object::String("NameError".to_string()),
+            },
+            ObjectTest {
+                input: "try { 1 / 0 } catch (e) { 0 }",
+                expected: Object::Integer(0),
+            },
+            ObjectTest {
+                input: "try { 1 / 0 } catch (e) { [error_kind(e), error_message(e)] }",
+                expected: Object::Array(vec![
+                    Object::String("ArithmeticError".to_string()),
+                    Object::String("division by zero".to_string()),
+                ]),
+            },
+            ObjectTest {
+                input: "try { 9223372036854775807 + 1 } catch (e) { error_message(e) }",
+                expected: Object::String("integer overflow: 9223372036854775807 + 1".to_string()),
+            },
+            ObjectTest {
+                input: "let min = -9223372036854775807 - 1; try { min / -1 } catch (e) { error_message(e) }",
+                expected: Object::String("integer overflow: -9223372036854775808 / -1".to_string()),
+            },
+            ObjectTest {
+                input: "let min = -9223372036854775807 - 1; try { -min } catch (e) { error_kind(e) }",
+                expected: Object::String("ArithmeticError".to_string()),
+            },
+            ObjectTest {
+                input: "try { 1 } catch (e) { 2 }",
+                expected: Object::Integer(1),
+            },
+            ObjectTest {
+                input: "let x = 1; try { throw 1; } catch (e) { } finally { let x = 2; } x",
+                expected: Object::Integer(2),
+            },
+            ObjectTest {
+                input: "let e = 1; try { throw 2; } catch (e) { e }; e",
+                expected: Object::Integer(1),
+            },
+            ObjectTest {
+                input: "let e = 1; let f = fn() { try { throw 2; } catch (e) { } e }; f()",
+                expected: Object::Integer(1),
+            },
+            ObjectTest {
+                input: "let f = fn() { try { return 1; } finally { puts(2); } 3 }; f()",
+                expected: Object::Integer(1),
+            },
+            ObjectTest {
+                input: "let f = fn() { try { 1 } finally { return 2; } }; f()",
+                expected: Object::Integer(2),
+            },
+            ObjectTest {
+                input: "let f = fn() { throw \"bad\" }; \
+                        let g = fn() { try { f() } catch (e) { throw e; } }; \
+                        try { g() } catch (e) { error_trace(e) }",
+                expected: Object::Array(vec![
+                    Object::String("in f, called at line 1".to_string()),
+                    Object::String("in g, called at line 1".to_string()),
+                ]),
+            },
+            ObjectTest {
+                input: "let f = fn(n) { if (n == 0) { throw \"done\" } f(n - 1) }; \
+                        try { f(3) } catch (e) { error_message(e) }",
+                expected: Object::String("done".to_string()),
+            },
+        ];
+
+        for test in tests {
+            let obj = test_eval(test.input).unwrap();
+            assert_eq!(
+                obj, test.expected,
+                "object doesnt match expected: {:?}, {:?}",
+                obj, test.expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_throw_errors() {
+        let tests = vec![
+            ErrorTest {
+                input: "throw \"boom\";",
+                expected: "boom",
+            },
+            ErrorTest {
+                input: "try { throw 1; } finally { 2 }",
+                expected: "1",
+            },
+            ErrorTest {
+                input: "try { throw 1; } catch (e) { throw 2; }",
+                expected: "2",
+            },
+            ErrorTest {
+                input: "try { 1 } finally { 1 + true }",
+                expected: "type mismatch: INTEGER + BOOLEAN",
+            },
+            ErrorTest {
+                input: "let f = fn() { throw \"boom\" }; f();",
+                expected: "boom\n  in f, called at line 1",
+            },
+            ErrorTest {
+                input: "try { throw 1; } catch (caught) { 2 }; caught",
+                expected: "identifier not found: caught",
+            },
+            ErrorTest {
+                input: "error_kind(1)",
+                expected: "argument to error_kind must be ERROR, got INTEGER",
+            },
+        ];
+
+        for test in tests {
+            let err = test_eval(test.input).unwrap_err();
+            assert_eq!(err.to_string(), test.expected);
+        }
+    }
+
+    #[test]
+    fn test_results() {
+        let check = "let check = fn(n) { if (n < 0) { err(\"negative\") } else { ok(n) } };";
+        let negative = || Object::Err(Box::new(Object::String("negative".to_string())));
+        let tests = vec![
+            ("ok(1)", Object::Ok(Box::new(Object::Integer(1)))),
+            ("is_ok(ok(1))", Object::Boolean(true)),
+            ("is_ok(err(1))", Object::Boolean(false)),
+            ("unwrap_or(ok(1), 2)", Object::Integer(1)),
+            ("unwrap_or(err(1), 2)", Object::Integer(2)),
+            (
+                "let add = fn(a, b) { ok(check(a)? + check(b)?) }; add(1, 2)",
+                Object::Ok(Box::new(Object::Integer(3))),
+            ),
+            (
+                "let add = fn(a, b) { ok(check(a)? + check(b)?) }; add(1, -2)",
+                negative(),
+            ),
+            (
+                "let f = fn() { let x = check(-1)?; puts(x); ok(x) }; unwrap_or(f(), 0)",
+                Object::Integer(0),
+            ),
+            (
+                "let f = fn() { try { check(-1)? } finally { 1 } }; f()",
+                negative(),
+            ),
+            (
+                "let f = fn() { try { check(-1)? } catch (e) { 1 } }; f()",
+                negative(),
+            ),
+            ("check(-1)?; 1", negative()),
+        ];
+
+        for (input, expected) in tests {
+            let obj = test_eval(&format!("{check} {input}")).unwrap();
+            assert_eq!(obj, expected, "{}", input);
+        }
+
+        let tests = vec![
+            ErrorTest {
+                input: "1?",
+                expected: "operand of ? must be OK or ERR, got INTEGER",
+            },
+            ErrorTest {
+                input: "is_ok(1)",
+                expected: "argument to is_ok must be OK or ERR, got INTEGER",
+            },
+            ErrorTest {
+                input: "unwrap_or(ok(1))",
+                expected: "wrong number of arguments to unwrap_or: got 1, want 2",
+            },
+        ];
+        for test in tests {
+            let err = test_eval(test.input).unwrap_err();
+            assert_eq!(err.to_string(), test.expected);
+        }
+    }
+
+    #[test]
+    fn test_match() {
+        let int = |n| Object::Integer(n);
+        let string = |s: &str| Object::String(s.to_string());
+        let globals = || {
+            let mut env = Environment::default();
+            env.set(
+                "pair".to_string(),
+                Object::Array(vec![int(1), int(2), int(3)]),
+            );
+            env.set(
+                "person".to_string(),
+                Object::Hash(BTreeMap::from([
+                    (HashKey::String("name".to_string()), string("ada")),
+                    (HashKey::String("age".to_string()), int(36)),
+                ])),
+            );
+            env
+        };
+
+        let tests = vec![
+            ("match (2) { 1 => \"one\", 2 => \"two\", _ => \"many\" }", string("two")),
+            ("match (5) { 1 => \"one\", _ => \"many\" }", string("many")),
+            ("match (-1) { -1 => true, _ => false }", Object::Boolean(true)),
+            ("match (5) { n if n > 3 => n * 2, n => n }", int(10)),
+            ("match (2) { n if n > 3 => n * 2, n => n }", int(2)),
+            ("match (pair) { [a, b] => a, [a, b, c] => a + b + c }", int(6)),
+            ("match (pair) { [1, ...rest] => rest, _ => 0 }", Object::Array(vec![int(2), int(3)])),
+            ("match (pair) { [2, ..._] => 1, [_, _, _] => 2 }", int(2)),
+            ("match (person) { {name: \"bob\"} => 1, {name, age} => age }", int(36)),
+            ("match (person) { {email} => email, {name: who} => who }", string("ada")),
+            ("match (1) { [a] => a, {a} => a, 1 => 0 }", int(0)),
+            (
+                "let fib = fn(n) { match (n) { 0 => 0, 1 => 1, n => fib(n - 1) + fib(n - 2) } }; fib(10)",
+                int(55),
+            ),
+            (
+                "let count = fn(n, acc) { match (n) { 0 => acc, _ => count(n - 1, acc + 1) } }; count(5000, 0)",
+                int(5000),
+            ),
+            (
+                "match (pair) { [a, ...rest] => { let b = a * 10; b + a }, _ => 0 }",
+                int(11),
+            ),
+            (
+                "match (1) { _ => ({\"a\": 1}) }",
+                Object::Hash(BTreeMap::from([(HashKey::String("a".to_string()), int(1))])),
+            ),
+            (
+                "let f = fn(n) { match (n) { 0 => { return 7; }, _ => n }; n * 2 }; [f(0), f(3)]",
+                Object::Array(vec![int(7), int(6)]),
+            ),
+            (
+                "let count = fn(n, acc) { match (n) { 0 => acc, _ => { let m = n - 1; count(m, acc + 1) } } }; count(5000, 0)",
+                int(5000),
+            ),
+        ];
+
+        let mut evaluator = Evaluator::new().with_max_call_depth(100);
+        for (input, expected) in tests {
+            let program = Parser::new(Lexer::new(input)).parse_program();
+            assert!(program.errors.is_empty(), "{:?}", program.errors);
+            let obj = evaluator.eval(program, &mut globals()).unwrap();
+            assert_eq!(obj, expected, "{}", input);
+        }
+
+        // arms bind in a scope of their own, whether they match or not
+        let input = "let n = 10; match (5) { n if n > 7 => 1, _ => 2 }; match (6) { n => n }; n";
+        assert_eq!(test_eval(input).unwrap(), int(10));
+        let err = test_eval("match (5) { m => m }; m").unwrap_err();
+        assert_eq!(err.to_string(), "identifier not found: m");
+
+        // only a mismatch of the arm's own pattern skips it
+        let bad = "let bad = fn() { let [a] = 5; a };";
+        for arm in ["[a, b, c, d = bad()] => d", "n if bad() => n"] {
+            let input = format!("{} match (pair) {{ {}, _ => 0 }}", bad, arm);
+            let program = Parser::new(Lexer::new(&input)).parse_program();
+            let err = evaluator.eval(program, &mut globals()).unwrap_err();
+            assert_eq!(
+                err.root_cause().to_string(),
+                "cannot destructure INTEGER as an array"
+            );
+        }
+
+        let err = test_eval("match (3) { 1 => 1, n if n < 3 => n }").unwrap_err();
+        assert_eq!(err.to_string(), "no match arm for 3");
+    }
+
+    #[test]
+    fn test_pattern_programs() {
+        let string = |s: &str| Object::String(s.to_string());
+        let tests = vec![
+            ObjectTest {
+                input: "let describe = fn(shape) {
+                    match (shape) {
+                        {kind: \"circle\", r} => \"circle \" + r,
+                        {w, h} if w == h => \"square\",
+                        {w, h = 1} => \"rect\",
+                        _ => \"unknown\",
+                    }
+                };
+                [
+                    describe({\"kind\": \"circle\", \"r\": \"1\"}),
+                    describe({\"w\": 2, \"h\": 2}),
+                    describe({\"w\": 2}),
+                    describe([]),
+                ]",
+                expected: Object::Array(vec![
+                    string("circle 1"),
+                    string("square"),
+                    string("rect"),
+                    string("unknown"),
+                ]),
+            },
+            ObjectTest {
+                input: "let sum = fn(xs) { match (xs) { [] => 0, [x, ...rest] => x + sum(rest) } };
+                sum([1, 2, 3, 4])",
+                expected: Object::Integer(10),
+            },
+            ObjectTest {
+                input: "let [first, [second], ...others] = [1, [2], 3, 4]; [first, second, others]",
+                expected: Object::Array(vec![
+                    Object::Integer(1),
+                    Object::Integer(2),
+                    Object::Array(vec![Object::Integer(3), Object::Integer(4)]),
+                ]),
+            },
+            ObjectTest {
+                input: "let {name, nick = \"none\"} = {\"name\": \"ada\"}; name + \" \" + nick",
+                expected: string("ada none"),
+            },
+        ];
+        for test in tests {
+            assert_eq!(
+                test_eval(test.input).unwrap(),
+                test.expected,
+                "{}",
+                test.input
+            );
+        }
+
+        let tests = vec![ErrorTest {
+            input: "let [a, b] = [1, 2, 3];",
+            expected: "cannot destructure an array of length 3 into 2 elements",
+        }];
+        for test in tests {
+            let err = test_eval(test.input).unwrap_err();
+            assert_eq!(err.to_string(), test.expected, "{}", test.input);
+        }
+    }
+
+    #[test]
+    fn test_destructuring_let() {
+        let int = |n| Object::Integer(n);
+        let string = |s: &str| Object::String(s.to_string());
+        let hash = |pairs: Vec<(&str, Object)>| {
+            Object::Hash(
+                pairs
+                    .into_iter()
+                    .map(|(key, value)| (HashKey::String(key.to_string()), value))
+                    .collect(),
+            )
+        };
+        // every case starts from these, so none sees the names another one bound
+        let globals = || {
+            let mut env = Environment::default();
+            env.set(
+                "list".to_string(),
+                Object::Array(vec![int(1), int(2), int(3)]),
+            );
+            env.set(
+                "nested".to_string(),
+                Object::Array(vec![int(1), Object::Array(vec![int(2), int(3)])]),
+            );
+            env.set(
+                "person".to_string(),
+                hash(vec![
+                    ("name", string("ada")),
+                    ("address", hash(vec![("city", string("london"))])),
+                ]),
+            );
+            env
+        };
+
+        let tests = vec![
+            ("let [a, b, ...rest] = list; a + b", int(3)),
+            (
+                "let [a, b, ...rest] = list; rest",
+                Object::Array(vec![int(3)]),
+            ),
+            (
+                "let [a, ...rest] = nested; rest",
+                Object::Array(vec![Object::Array(vec![int(2), int(3)])]),
+            ),
+            (
+                "let [_, _, ...rest] = list; rest",
+                Object::Array(vec![int(3)]),
+            ),
+            ("let [x, [y, z]] = nested; x + y + z", int(6)),
+            (
+                "let {name, address: {city}} = person; name + \" of \" + city",
+                string("ada of london"),
+            ),
+            ("let [a, b, c, d = a + 10] = list; d", int(11)),
+            ("let [a, d = a + 10] = [5]; d", int(15)),
+            ("let {x, y = x * 2} = {\"x\": 3}; y", int(6)),
+            ("let [a = 1, b = a + 1, c = b * 10] = []; c", int(20)),
+            ("let {nick = \"none\"} = person; nick", string("none")),
+            ("let {name = \"none\"} = person; name", string("ada")),
+            ("let {address: {zip = 0}} = person; zip", int(0)),
+            (
+                "try { let [q] = 5; } catch (e) { error_kind(e) }",
+                string("TypeError"),
+            ),
+        ];
+
+        let mut evaluator = Evaluator::new();
+        for (input, expected) in tests {
+            let program = Parser::new(Lexer::new(input)).parse_program();
+            assert!(program.errors.is_empty(), "{:?}", program.errors);
+            let obj = evaluator.eval(program, &mut globals()).unwrap();
+            assert_eq!(obj, expected, "{}", input);
+        }
+
+        let tests = vec![
+            (
+                "let [a, b] = list;",
+                "cannot destructure an array of length 3 into 2 elements",
+            ),
+            (
+                "let [a, b, c, d] = list;",
+                "cannot destructure an array of length 3 into 4 elements",
+            ),
+            (
+                "let [a, b, c, d, ...e] = list;",
+                "cannot destructure an array of length 3 into at least 4 elements",
+            ),
+            (
+                "let {email} = person;",
+                "cannot destructure a hash without the key `email`",
+            ),
+            (
+                "let {address: [city]} = person;",
+                "cannot destructure HASH as an array",
+            ),
+            ("let {name} = list;", "cannot destructure ARRAY as a hash"),
+            (
+                "let [1, ...r] = nested; let [2, ...r] = nested;",
+                "cannot destructure 1 as 2",
+            ),
+            (
+                "let [w, x, y] = list; let [_, [w, x, y]] = nested; w",
+                "cannot destructure an array of length 2 into 3 elements",
+            ),
+        ];
+        for (input, expected) in tests {
+            let program = Parser::new(Lexer::new(input)).parse_program();
+            let err = evaluator.eval(program, &mut globals()).unwrap_err();
+            assert_eq!(err.to_string(), expected, "{}", input);
+        }
+
+        // a `let` that fails binds none of its names
+        let mut env = globals();
+        let input = "let [w, x, y] = list; let [_, [w, x, y]] = nested;";
+        let program = Parser::new(Lexer::new(input)).parse_program();
+        evaluator.eval(program, &mut env).unwrap_err();
+        assert_eq!(env.get("w"), Some(int(1)));
+        // nor do the defaults it evaluated
+        let input = "let [a, b = a, c] = [1];";
+        let program = Parser::new(Lexer::new(input)).parse_program();
+        evaluator.eval(program, &mut env).unwrap_err();
+        assert_eq!(env.get("a"), None);
+    }
+
+    #[test]
+    fn test_stop_errors_are_not_caught() {
+        let input = "let spin = fn() { spin() }; try { spin() } catch (e) { 1 } finally { 2 }";
+        let err = test_eval_budget(
+            input,
+            EvalBudget {
+                max_steps: Some(1000),
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<EvalError>(),
+            Some(&EvalError::BudgetExceeded(BudgetLimit::Steps(1000)))
+        );
+    }
+
+    #[test]
+    fn test_builtin_io() {
+        let output = SharedWriter::new(Vec::new());
+        let mut evaluator = Evaluator::new()
+            .with_output(output.clone())
+            .with_input(Cursor::new("first\r\nsecond\n"));
+        let mut env = Environment::default();
+
+        let tests = vec![
+            ObjectTest {
+                input: "puts(\"hello\", 5, true);",
+                expected: Object::Null,
+            },
+            ObjectTest {
+                input: "gets();",
+                expected: Object::String("first".to_string()),
+            },
+            ObjectTest {
+                input: "let echo = fn() { puts(gets()) }; echo();",
+                expected: Object::Null,
+            },
+            ObjectTest {
+                input: "gets();",
+                expected: Object::Null,
+            },
+            ObjectTest {
+                input: "let puts = fn(x) { x }; puts(1);",
+                expected: Object::Integer(1),
+            },
+        ];
+
+        for test in tests {
+            let program = Parser::new(Lexer::new(test.input)).parse_program();
+            let obj = evaluator.eval(program, &mut env).unwrap();
+            assert_eq!(
+                obj, test.expected,
+                "object doesnt match expected: {:?}, {:?}",
+                obj, test.expected
+            );
+        }
+        assert_eq!(
+            String::from_utf8(output.lock().clone()).unwrap(),
+            "hello\n5\ntrue\nsecond\n"
+        );
+
+        let program = Parser::new(Lexer::new("gets(1);")).parse_program();
+        assert_eq!(
+            evaluator.eval(program, &mut env).unwrap_err().to_string(),
+            "wrong number of arguments to gets: got 1, want 0"
+        );
+    }
 }
@@ -0,0 +1,261 @@
+//! C API for embedding the interpreter in other languages, declared in
+//! `include/monkey.h`, which `build.rs` generates from this file.
+//!
+//! Every pointer returned here is owned by the caller and released with the matching
+//! `*_free` function. Strings are UTF-8 and NUL terminated.
+
+use std::{
+    ffi::{c_char, c_int, CStr, CString},
+    panic::{catch_unwind, AssertUnwindSafe},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::{interpreter::Interpreter, object::Object};
+
+/// Outcome of [`monkey_eval`]: the value of the script or the error it failed with.
+pub struct MonkeyResult {
+    result: Result<Object, String>,
+}
+
+/// Creates an interpreter whose globals persist between calls to [`monkey_eval`].
+#[no_mangle]
+pub extern "C" fn monkey_interpreter_new() -> *mut Interpreter {
+    Box::into_raw(Box::new(Interpreter::new()))
+}
+
+/// # Safety
+///
+/// `interpreter` must be null or come from [`monkey_interpreter_new`] and not have been
+/// freed already.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_interpreter_free(interpreter: *mut Interpreter) {
+    if !interpreter.is_null() {
+        drop(Box::from_raw(interpreter));
+    }
+}
+
+/// Evaluates `source`, never returning null. Null arguments, invalid UTF-8 and
+/// panics are reported as errors of the result.
+///
+/// # Safety
+///
+/// `interpreter` must be null or a live pointer from [`monkey_interpreter_new`], and
+/// `source` must be null or point to a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_eval(
+    interpreter: *mut Interpreter,
+    source: *const c_char,
+) -> *mut MonkeyResult {
+    let result = eval(interpreter, source).map_err(|err| err.to_string());
+    Box::into_raw(Box::new(MonkeyResult { result }))
+}
+
+unsafe fn eval(interpreter: *mut Interpreter, source: *const c_char) -> Result<Object> {
+    let Some(interpreter) = interpreter.as_mut() else {
+        return Err(anyhow!("interpreter is null"));
+    };
+    if source.is_null() {
+        return Err(anyhow!("source is null"));
+    }
+    let source = CStr::from_ptr(source).to_str()?;
+
+    catch_unwind(AssertUnwindSafe(|| interpreter.eval_str(source)))
+        .unwrap_or_else(|_| Err(anyhow!("interpreter panicked")))
+}
+
+/// Returns 1 if the evaluation failed, 0 otherwise.
+///
+/// # Safety
+///
+/// `result` must be a live pointer from [`monkey_eval`].
+#[no_mangle]
+pub unsafe extern "C" fn monkey_result_is_error(result: *const MonkeyResult) -> c_int {
+    (*result).result.is_err() as c_int
+}
+
+/// The value as `puts` would print it, or the error message. Release it with
+/// [`monkey_string_free`].
+///
+/// # Safety
+///
+/// `result` must be a live pointer from [`monkey_eval`].
+#[no_mangle]
+pub unsafe extern "C" fn monkey_result_to_string(result: *const MonkeyResult) -> *mut c_char {
+    let text = match &(*result).result {
+        Ok(obj) => obj.to_string(),
+        Err(err) => err.clone(),
+    };
+    // interior NULs can only come from script strings, cut the text there like C would
+    let text = match CString::new(text) {
+        Ok(text) => text,
+        Err(err) => {
+            let nul = err.nul_position();
+            let mut bytes = err.into_vec();
+            bytes.truncate(nul);
+            CString::new(bytes).expect("truncated at the first NUL")
+        }
+    };
+    text.into_raw()
+}
+
+/// Stores the value in `out` and returns 1 if it is an integer, returns 0 otherwise.
+///
+/// # Safety
+///
+/// `result` must be a live pointer from [`monkey_eval`] and `out` must be null or
+/// valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_result_to_int(result: *const MonkeyResult, out: *mut i64) -> c_int {
+    match (*result).result {
+        Ok(Object::Integer(n)) => {
+            if !out.is_null() {
+                *out = n;
+            }
+            1
+        }
+        _ => 0,
+    }
+}
+
+/// # Safety
+///
+/// `result` must be null or come from [`monkey_eval`] and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_result_free(result: *mut MonkeyResult) {
+    if !result.is_null() {
+        drop(Box::from_raw(result));
+    }
+}
+
+/// # Safety
+///
+/// `text` must be null or come from [`monkey_result_to_string`] and not have been freed
+/// already.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_string_free(text: *mut c_char) {
+    if !text.is_null() {
+        drop(CString::from_raw(text));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        ffi::{CStr, CString},
+        ptr,
+    };
+
+    use super::*;
+
+    fn eval_to_string(interpreter: *mut Interpreter, source: &str) -> (bool, String) {
+        let source = CString::new(source).unwrap();
+        unsafe {
+            let result = monkey_eval(interpreter, source.as_ptr());
+            let is_error = monkey_result_is_error(result) == 1;
+            let text = monkey_result_to_string(result);
+            let string = CStr::from_ptr(text).to_str().unwrap().to_string();
+            monkey_string_free(text);
+            monkey_result_free(result);
+            (is_error, string)
+        }
+    }
+
+    #[test]
+    fn test_eval() {
+        let interpreter = monkey_interpreter_new();
+
+        let tests = vec![
+            ("let double = fn(x) { x * 2 };", (false, "null")),
+            ("double(21)", (false, "42")),
+            ("\"mon\" + \"key\"", (false, "monkey")),
+            (
+                "double(true)",
+                (
+                    true,
+                    "type mismatch: BOOLEAN * INTEGER\n  in double, called at line 1",
+                ),
+            ),
+            ("missing", (true, "identifier not found: missing")),
+        ];
+        for (input, (is_error, text)) in tests {
+            assert_eq!(
+                eval_to_string(interpreter, input),
+                (is_error, text.to_string()),
+                "{}",
+                input
+            );
+        }
+
+        unsafe { monkey_interpreter_free(interpreter) };
+    }
+
+    #[test]
+    fn test_result_to_int() {
+        let interpreter = monkey_interpreter_new();
+        unsafe {
+            let mut n = 0;
+            let result = monkey_eval(interpreter, c"6 * 7".as_ptr());
+            assert_eq!(monkey_result_to_int(result, &mut n), 1);
+            assert_eq!(n, 42);
+            monkey_result_free(result);
+
+            let result = monkey_eval(interpreter, c"true".as_ptr());
+            assert_eq!(monkey_result_to_int(result, &mut n), 0);
+            assert_eq!(n, 42);
+            monkey_result_free(result);
+
+            monkey_interpreter_free(interpreter);
+        }
+    }
+
+    #[test]
+    fn test_null_arguments() {
+        unsafe {
+            let result = monkey_eval(ptr::null_mut(), c"1".as_ptr());
+            assert_eq!(monkey_result_is_error(result), 1);
+            monkey_result_free(result);
+
+            let interpreter = monkey_interpreter_new();
+            let result = monkey_eval(interpreter, ptr::null());
+            assert_eq!(monkey_result_is_error(result), 1);
+            monkey_result_free(result);
+            monkey_interpreter_free(interpreter);
+
+            monkey_interpreter_free(ptr::null_mut());
+            monkey_result_free(ptr::null_mut());
+            monkey_string_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_eval_after_panic() {
+        let interpreter = monkey_interpreter_new();
+        unsafe {
+            (*interpreter).register_function("boom", |_| panic!("boom"));
+        }
+        eval_to_string(
+            interpreter,
+            "let f = fn(n) { if (n == 0) { boom() } else { 1 + f(n - 1) } };",
+        );
+        assert_eq!(
+            eval_to_string(interpreter, "f(900)"),
+            (true, "interpreter panicked".to_string())
+        );
+
+        eval_to_string(
+            interpreter,
+            "let d = fn(n) { if (n == 0) { missing } else { 1 + d(n - 1) } };",
+        );
+        let (is_error, text) = eval_to_string(interpreter, "d(900)");
+        assert!(is_error);
+        assert!(
+            text.starts_with("identifier not found: missing"),
+            "{}",
+            text
+        );
+        assert!(!text.contains("in f,"), "{}", text);
+
+        unsafe { monkey_interpreter_free(interpreter) };
+    }
+}
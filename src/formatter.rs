@@ -0,0 +1,436 @@
+use anyhow::{bail, Result};
+
+use crate::{
+    ast::{
+        ArmBody, BlockStatement, CallableExpression, Expression, FunctionLiteral, Program,
+        Statement,
+    },
+    interpreter::ParseError,
+    lexer::Lexer,
+    parser::{OperatorPrecedence, Parser},
+    token::Comment,
+};
+
+const INDENT: &str = "    ";
+
+/// Rewrites `source` in the canonical layout: one statement per line, four space
+/// indentation, at most one blank line in a row. Comments are kept, a comment in the
+/// middle of a statement moves to the line after it. Sources that do not parse are
+/// reported as [`ParseError`].
+///
+/// ```
+/// use thorsten_interpreter::formatter::format_source;
+///
+/// let source = "let max=fn(a,b){ // larger\nif(a>b){a}else{b}};";
+/// assert_eq!(
+///     format_source(source)?,
+///     "let max = fn(a, b) {\n    // larger\n    if (a > b) {\n        a\n    } else {\n        b\n    }\n};\n"
+/// );
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn format_source(source: &str) -> Result<String> {
+    let program = Parser::new(Lexer::new(source)).parse_program();
+    if !program.errors.is_empty() {
+        bail!(ParseError {
+            errors: program.errors
+        });
+    }
+    Ok(format_program(&program, source))
+}
+
+/// Formats a program parsed from `source`, which is read to keep blank lines.
+pub fn format_program(program: &Program, source: &str) -> String {
+    let mut formatter = Formatter {
+        source,
+        comments: &program.comments,
+        next_comment: 0,
+        out: String::new(),
+        indent: 0,
+        last_end: None,
+    };
+    formatter.statements(&program.statments, source.len(), false);
+    formatter.out
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    comments: &'a [Comment],
+    next_comment: usize,
+    out: String,
+    indent: usize,
+    // end of the last statement or comment written in the current block
+    last_end: Option<usize>,
+}
+
+impl Formatter<'_> {
+    fn new_line(&mut self) {
+        self.out.push('\n');
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    fn peek_comment(&self) -> Option<&Comment> {
+        self.comments.get(self.next_comment)
+    }
+
+    /// Writes the comments starting before `offset`, each on its own line.
+    fn comments_before(&mut self, offset: usize) {
+        while let Some(comment) = self.peek_comment() {
+            if comment.span.start >= offset {
+                break;
+            }
+            let (text, span) = (format!("//{}", comment.text), comment.span);
+            self.next_comment += 1;
+            self.blank_line_before(span.start);
+            self.new_line();
+            self.out.push_str(&text);
+            self.last_end = Some(span.end);
+        }
+    }
+
+    /// Keeps one empty line where the source had any since the last thing written.
+    fn blank_line_before(&mut self, start: usize) {
+        // comments from inside the last statement are written after it
+        let Some(end) = self.last_end.filter(|end| *end < start) else {
+            return;
+        };
+        let lines: Vec<_> = self.source[end..start].split('\n').collect();
+        if lines.len() > 2
+            && lines[1..lines.len() - 1]
+                .iter()
+                .any(|l| l.trim().is_empty())
+        {
+            self.out.push('\n');
+        }
+    }
+
+    /// Writes `statements` one per line, then the comments left before `end`. In a block
+    /// the value of the last expression is returned, so it keeps no semicolon.
+    fn statements(&mut self, statements: &[Statement], end: usize, in_block: bool) {
+        self.last_end = None;
+        for (idx, statement) in statements.iter().enumerate() {
+            let span = statement.span();
+            self.comments_before(span.start);
+            self.blank_line_before(span.start);
+
+            self.new_line();
+            let is_last = idx + 1 == statements.len();
+            self.statement(statement, in_block && is_last);
+
+            self.last_end = Some(span.end);
+            // a comment after the next statement on the same line belongs to that one
+            let next_start = statements
+                .get(idx + 1)
+                .map_or(end, |next| next.span().start);
+            if let Some(comment) = self.peek_comment() {
+                let same_line = comment.span.start >= span.end
+                    && comment.span.start < next_start
+                    && !self.source[span.end..comment.span.start].contains('\n');
+                if same_line {
+                    let (text, end) = (format!(" //{}", comment.text), comment.span.end);
+                    self.next_comment += 1;
+                    self.out.push_str(&text);
+                    self.last_end = Some(end);
+                }
+            }
+        }
+        self.comments_before(end);
+
+        // the program starts without the line break written before each line
+        if !in_block && self.out.starts_with('\n') {
+            self.out.remove(0);
+            self.out.push('\n');
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement, is_block_value: bool) {
+        match statement {
+            Statement::Let(statement) => {
+                self.out.push_str(&format!("let {} = ", statement.pattern));
+                self.expression(&statement.value);
+                self.out.push(';');
+            }
+            Statement::Return(statement) => {
+                self.out.push_str("return ");
+                self.expression(&statement.value);
+                self.out.push(';');
+            }
+            Statement::Throw(statement) => {
+                self.out.push_str("throw ");
+                self.expression(&statement.value);
+                self.out.push(';');
+            }
+            Statement::Expression(statement) => {
+                self.expression(&statement.expression);
+                // even after a block, as `if (x) { 1 }\n-1` would parse as a subtraction
+                if !is_block_value {
+                    self.out.push(';');
+                }
+            }
+            Statement::Block(block) => self.block(block),
+        }
+    }
+
+    fn block(&mut self, block: &BlockStatement) {
+        let has_comments = self
+            .peek_comment()
+            .is_some_and(|comment| comment.span.start < block.span.end);
+        if block.statements.is_empty() && !has_comments {
+            self.out.push_str("{}");
+            return;
+        }
+
+        self.out.push('{');
+        self.indent += 1;
+        self.statements(&block.statements, block.span.end, true);
+        self.indent -= 1;
+        self.new_line();
+        self.out.push('}');
+    }
+
+    fn operand(&mut self, exp: &Expression, wrap: bool) {
+        if wrap {
+            self.out.push('(');
+            self.expression(exp);
+            self.out.push(')');
+        } else {
+            self.expression(exp);
+        }
+    }
+
+    fn function(&mut self, literal: &FunctionLiteral) {
+        let parameters: Vec<_> = literal.parameters.iter().map(|p| p.to_string()).collect();
+        self.out
+            .push_str(&format!("fn({}) ", parameters.join(", ")));
+        self.block(&literal.body);
+    }
+
+    // same layout as `Display for Expression`, with blocks spread over lines
+    fn expression(&mut self, exp: &Expression) {
+        match exp {
+            Expression::Callable(CallableExpression::FunctionLiteral(literal)) => {
+                self.function(literal)
+            }
+            Expression::Prefix(exp) => {
+                self.out.push_str(&exp.operator.to_string());
+                let wrap = exp.right.precedence() < OperatorPrecedence::Prefix;
+                self.operand(&exp.right, wrap);
+            }
+            Expression::Propagate(exp) => {
+                self.operand(
+                    &exp.value,
+                    exp.value.precedence() < OperatorPrecedence::Call,
+                );
+                self.out.push('?');
+            }
+            Expression::Infix(exp) => {
+                let precedence: OperatorPrecedence = (&exp.operator).into();
+                self.operand(&exp.left, exp.left.precedence() < precedence);
+                self.out.push_str(&format!(" {} ", exp.operator));
+                self.operand(&exp.right, exp.right.precedence() <= precedence);
+            }
+            Expression::If(exp) => {
+                self.out.push_str("if (");
+                self.expression(&exp.condition);
+                self.out.push_str(") ");
+                self.block(&exp.consequence);
+                if let Some(alternative) = &exp.alternative {
+                    self.out.push_str(" else ");
+                    self.block(alternative);
+                }
+            }
+            Expression::Try(exp) => {
+                self.out.push_str("try ");
+                self.block(&exp.body);
+                if let Some(catch) = &exp.catch {
+                    self.out.push_str(&format!(" catch ({}) ", catch.name));
+                    self.block(&catch.body);
+                }
+                if let Some(finally) = &exp.finally {
+                    self.out.push_str(" finally ");
+                    self.block(finally);
+                }
+            }
+            Expression::Match(exp) => {
+                self.out.push_str("match (");
+                self.expression(&exp.value);
+                self.out.push_str(") {");
+                self.indent += 1;
+                for arm in &exp.arms {
+                    self.new_line();
+                    self.out.push_str(&arm.pattern.to_string());
+                    if let Some(guard) = &arm.guard {
+                        self.out.push_str(" if ");
+                        self.expression(guard);
+                    }
+                    self.out.push_str(" => ");
+                    match &arm.body {
+                        ArmBody::Expression(exp) => {
+                            self.operand(exp, matches!(exp, Expression::HashLiteral(_)))
+                        }
+                        ArmBody::Block(block) => self.block(block),
+                    }
+                    self.out.push(',');
+                }
+                self.indent -= 1;
+                if !exp.arms.is_empty() {
+                    self.new_line();
+                }
+                self.out.push('}');
+            }
+            Expression::ArrayLiteral(literal) => {
+                self.out.push('[');
+                for (idx, element) in literal.elements.iter().enumerate() {
+                    if idx > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.expression(element);
+                }
+                self.out.push(']');
+            }
+            Expression::HashLiteral(literal) => {
+                self.out.push('{');
+                for (idx, (key, value)) in literal.pairs.iter().enumerate() {
+                    if idx > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.expression(key);
+                    self.out.push_str(": ");
+                    self.expression(value);
+                }
+                self.out.push('}');
+            }
+            Expression::Call(call) => {
+                match &call.func {
+                    CallableExpression::FunctionLiteral(literal) => {
+                        self.out.push('(');
+                        self.function(literal);
+                        self.out.push(')');
+                    }
+                    CallableExpression::Identifier(ident) => self.out.push_str(&ident.value),
+                }
+                self.out.push('(');
+                for (idx, argument) in call.arguments.iter().enumerate() {
+                    if idx > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.expression(argument);
+                }
+                self.out.push(')');
+            }
+            Expression::Callable(CallableExpression::Identifier(_))
+            | Expression::IntegerLiteral(_)
+            | Expression::StringLiteral(_)
+            | Expression::BooleanLiteral(_) => self.out.push_str(&exp.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{interpreter::Interpreter, shared_io::SharedWriter};
+
+    use super::format_source;
+
+    #[test]
+    fn test_format_source() {
+        let tests = vec![
+            ("", ""),
+            ("let   x=5", "let x = 5;\n"),
+            ("puts( 1 )\nputs(2);", "puts(1);\nputs(2);\n"),
+            ("let a = 1;\n\n\n\nlet b = 2;", "let a = 1;\n\nlet b = 2;\n"),
+            ("let f = fn() {};", "let f = fn() {};\n"),
+            (
+                "let xs = [ 1,fn(x) { x }, {\"a\" :[]} ];",
+                "let xs = [1, fn(x) {\n    x\n}, {\"a\": []}];\n",
+            ),
+            (
+                "let add = fn(a, b) { let sum = a + b; return sum; };",
+                "let add = fn(a, b) {\n    let sum = a + b;\n    return sum;\n};\n",
+            ),
+            (
+                "let twice = fn(f, x) { f(f(x)) }; twice(fn(x) { x * 2 }, 1)",
+                "let twice = fn(f, x) {\n    f(f(x))\n};\ntwice(fn(x) {\n    x * 2\n}, 1);\n",
+            ),
+            (
+                "if (x) { puts(1); 2 } else { 3 }",
+                "if (x) {\n    puts(1);\n    2\n} else {\n    3\n};\n",
+            ),
+            ("(a + b) * -(c) - (d - e)", "(a + b) * -c - (d - e);\n"),
+            (
+                "// leading\n\n// attached\nlet x = 1; // trailing\nlet y = fn() {\n  // inside\n};\n// last",
+                "// leading\n\n// attached\nlet x = 1; // trailing\nlet y = fn() {\n    // inside\n};\n// last\n",
+            ),
+            (
+                "let f = fn(x) {\n    x\n    // before close\n};",
+                "let f = fn(x) {\n    x\n    // before close\n};\n",
+            ),
+            (
+                "let f = fn(x) { x } // after\nf(1) // call",
+                "let f = fn(x) {\n    x\n}; // after\nf(1); // call\n",
+            ),
+            (
+                "let f = fn() { 1 }; f(); // end",
+                "let f = fn() {\n    1\n};\nf(); // end\n",
+            ),
+            (
+                "add(1, // one\n 2);\nx",
+                "add(1, 2);\n// one\nx;\n",
+            ),
+            ("let  x = parse(s) ?;", "let x = parse(s)?;\n"),
+            ("let {name,age:years=0}=p", "let {name, age: years = 0} = p;\n"),
+            (
+                "match(x){ [a, ...r] if a>1=>a, _ =>fn(){ 1 }() }",
+                "match (x) {\n    [a, ...r] if a > 1 => a,\n    _ => (fn() {\n        1\n    })(),\n};\n",
+            ),
+            (
+                "match(x){ 1=>{ let y=2; y }, _=>({}) }",
+                "match (x) {\n    1 => {\n        let y = 2;\n        y\n    },\n    _ => ({}),\n};\n",
+            ),
+            (
+                "try { risky() } catch (e) { throw e } finally { done() }\nx",
+                "try {\n    risky()\n} catch (e) {\n    throw e;\n} finally {\n    done()\n};\nx;\n",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let formatted = format_source(input).unwrap();
+            assert_eq!(formatted, expected, "{:?}", input);
+            assert_eq!(
+                format_source(&formatted).unwrap(),
+                formatted,
+                "not idempotent"
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_keeps_behaviour() {
+        let run = |source: &str| {
+            let output = SharedWriter::new(Vec::new());
+            Interpreter::new()
+                .with_output(output.clone())
+                .eval_str(source)
+                .unwrap();
+            let printed = output.lock().clone();
+            String::from_utf8(printed).unwrap()
+        };
+
+        let sources = [
+            "let f = fn(x) {\nif (x) { 10 } else { 2 }; -1\n};\nputs(f(true));",
+            "let f = fn(x) { try { x } finally { 1 }; -1 }; puts(f(2));",
+            "let f = fn(x) { match (x) { _ => 3 }; -1 }; puts(f(2));",
+        ];
+        for source in sources {
+            let formatted = format_source(source).unwrap();
+            assert_eq!(run(&formatted), run(source), "{}", formatted);
+            assert_eq!(format_source(&formatted).unwrap(), formatted);
+        }
+    }
+
+    #[test]
+    fn test_format_parse_error() {
+        assert!(format_source("let = 1;").is_err());
+    }
+}
@@ -0,0 +1,566 @@
+use std::{
+    fmt::Display,
+    io::{BufRead, Write},
+};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    convert::IntoMonkey,
+    debugger::Debugger,
+    diagnostic::Diagnostic,
+    environment::Environment,
+    evaluator::{CancellationToken, EvalBudget, Evaluator, RuntimeError},
+    lexer::Lexer,
+    object::{HostFunction, Object},
+    parser::Parser,
+};
+
+/// Returned by [`Interpreter::eval_str`] when the source does not parse, holding every
+/// error reported by the parser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub errors: Vec<Diagnostic>,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let messages: Vec<_> = self.errors.iter().map(|err| err.to_string()).collect();
+        write!(f, "{}", messages.join("\n"))
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A Monkey session for Rust hosts: global bindings persist between calls to
+/// [`Interpreter::eval_str`], like lines typed into the REPL. An interpreter can be moved
+/// to another thread, and the values it returns shared between threads.
+///
+/// ```
+/// use thorsten_interpreter::{interpreter::Interpreter, object::Object};
+///
+/// let mut interpreter = Interpreter::new();
+/// interpreter.eval_str("let double = fn(x) { x * 2 };")?;
+///
+/// assert_eq!(interpreter.eval_str("double(21)")?, Object::Integer(42));
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Default)]
+pub struct Interpreter {
+    env: Environment,
+    evaluator: Evaluator,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Nested calls allowed, [`crate::DEFAULT_MAX_CALL_DEPTH`] by default.
+    pub fn with_max_call_depth(mut self, max_call_depth: usize) -> Self {
+        self.evaluator = self.evaluator.with_max_call_depth(max_call_depth);
+        self
+    }
+
+    /// Limits applied to each call of [`Interpreter::eval_str`] and
+    /// [`Interpreter::call_function`].
+    ///
+    /// ```
+    /// use thorsten_interpreter::{interpreter::Interpreter, BudgetLimit, EvalBudget, EvalError};
+    ///
+    /// let mut interpreter = Interpreter::new().with_budget(EvalBudget {
+    ///     max_steps: Some(10_000),
+    ///     ..Default::default()
+    /// });
+    /// let err = interpreter
+    ///     .eval_str("let forever = fn() { forever() }; forever();")
+    ///     .unwrap_err();
+    ///
+    /// assert_eq!(
+    ///     err.downcast_ref::<EvalError>(),
+    ///     Some(&EvalError::BudgetExceeded(BudgetLimit::Steps(10_000)))
+    /// );
+    /// ```
+    pub fn with_budget(mut self, budget: EvalBudget) -> Self {
+        self.evaluator = self.evaluator.with_budget(budget);
+        self
+    }
+
+    pub fn with_cancellation_token(mut self, cancellation: CancellationToken) -> Self {
+        self.evaluator = self.evaluator.with_cancellation_token(cancellation);
+        self
+    }
+
+    /// Where scripts print with `puts`, stdout by default. See
+    /// [`crate::shared_io::SharedWriter`] to capture it.
+    pub fn with_output(mut self, output: impl Write + Send + 'static) -> Self {
+        self.evaluator = self.evaluator.with_output(output);
+        self
+    }
+
+    /// Where scripts read lines from with `gets`, stdin by default.
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    ///
+    /// use thorsten_interpreter::{interpreter::Interpreter, object::Object};
+    ///
+    /// let mut interpreter = Interpreter::new().with_input(Cursor::new("ada\n"));
+    ///
+    /// assert_eq!(
+    ///     interpreter.eval_str("\"hello \" + gets()")?,
+    ///     Object::String("hello ada".to_string())
+    /// );
+    /// assert_eq!(interpreter.eval_str("gets()")?, Object::Null);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn with_input(mut self, input: impl BufRead + Send + 'static) -> Self {
+        self.evaluator = self.evaluator.with_input(input);
+        self
+    }
+
+    /// Parses and evaluates `source`, returning the value of its last statement, `null` if
+    /// it has none. Parse failures are reported as [`ParseError`] and nothing is
+    /// evaluated.
+    ///
+    /// ```
+    /// use thorsten_interpreter::interpreter::{Interpreter, ParseError};
+    ///
+    /// let err = Interpreter::new().eval_str("let = 5;").unwrap_err();
+    /// assert!(err.downcast_ref::<ParseError>().is_some());
+    /// ```
+    pub fn eval_str(&mut self, source: &str) -> Result<Object> {
+        let program = Parser::new(Lexer::new(source)).parse_program();
+        if !program.errors.is_empty() {
+            bail!(ParseError {
+                errors: program.errors
+            });
+        }
+        if program.statments.is_empty() {
+            return Ok(Object::Null);
+        }
+        self.evaluator.eval(program, &mut self.env)
+    }
+
+    /// Evaluates `source` like [`Interpreter::eval_str`], pausing in `debugger` at its
+    /// breakpoints and steps.
+    pub fn debug_str(&mut self, source: &str, debugger: Debugger) -> Result<Object> {
+        self.evaluator
+            .set_debugger(Some(debugger.with_source(source)));
+        let result = self.eval_str(source);
+        self.evaluator.set_debugger(None);
+        result
+    }
+
+    /// ```
+    /// use thorsten_interpreter::{interpreter::Interpreter, object::Object};
+    ///
+    /// let mut interpreter = Interpreter::new();
+    /// interpreter.eval_str("let answer = 6 * 7;")?;
+    ///
+    /// assert_eq!(interpreter.get_global("answer"), Some(Object::Integer(42)));
+    /// assert_eq!(interpreter.get_global("question"), None);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn get_global(&self, name: &str) -> Option<Object> {
+        self.env.get(name)
+    }
+
+    /// Every global binding, sorted by name.
+    ///
+    /// ```
+    /// use thorsten_interpreter::{interpreter::Interpreter, object::Object};
+    ///
+    /// let mut interpreter = Interpreter::new();
+    /// interpreter.eval_str("let b = true; let a = 1;")?;
+    ///
+    /// assert_eq!(
+    ///     interpreter.globals(),
+    ///     vec![
+    ///         ("a".to_string(), Object::Integer(1)),
+    ///         ("b".to_string(), Object::Boolean(true)),
+    ///     ]
+    /// );
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn globals(&self) -> Vec<(String, Object)> {
+        self.env.bindings()
+    }
+
+    /// Forgets every global binding, keeping the configured limits and streams.
+    pub fn reset(&mut self) {
+        self.env = Environment::default();
+    }
+
+    /// Binds `name` for every script evaluated afterwards, returning the previous value.
+    ///
+    /// ```
+    /// use thorsten_interpreter::{interpreter::Interpreter, object::Object};
+    ///
+    /// let mut interpreter = Interpreter::new();
+    /// interpreter.set_global("greeting", "hello");
+    ///
+    /// assert_eq!(
+    ///     interpreter.eval_str("greeting + \" world\"")?,
+    ///     Object::String("hello world".to_string())
+    /// );
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn set_global(
+        &mut self,
+        name: impl Into<String>,
+        value: impl IntoMonkey,
+    ) -> Option<Object> {
+        self.env.set(name.into(), value.into_monkey())
+    }
+
+    /// Exposes a Rust closure to scripts as a global function called `name`.
+    ///
+    /// ```
+    /// use anyhow::bail;
+    /// use thorsten_interpreter::{interpreter::Interpreter, object::Object};
+    ///
+    /// let mut interpreter = Interpreter::new();
+    /// interpreter.register_function("lookup_user", |args| match args {
+    ///     [Object::Integer(1)] => Ok(Object::String("ada".to_string())),
+    ///     [Object::Integer(_)] => Ok(Object::Null),
+    ///     _ => bail!("lookup_user expects a user id"),
+    /// });
+    ///
+    /// assert_eq!(
+    ///     interpreter.eval_str("lookup_user(1) + \"!\"")?,
+    ///     Object::String("ada!".to_string())
+    /// );
+    /// assert!(interpreter.eval_str("lookup_user(true)").is_err());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn register_function<F>(&mut self, name: &str, func: F) -> Option<Object>
+    where
+        F: Fn(&[Object]) -> Result<Object> + Send + Sync + 'static,
+    {
+        self.set_global(name, Object::HostFunction(HostFunction::new(name, func)))
+    }
+
+    /// Calls the function bound to `name` with `args`.
+    ///
+    /// ```
+    /// use thorsten_interpreter::{interpreter::Interpreter, object::Object};
+    ///
+    /// let mut interpreter = Interpreter::new();
+    /// interpreter.eval_str("let add = fn(x, y) { x + y };")?;
+    ///
+    /// let sum = interpreter.call_function("add", &[Object::Integer(1), Object::Integer(2)])?;
+    /// assert_eq!(sum, Object::Integer(3));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn call_function(&mut self, name: &str, args: &[Object]) -> Result<Object> {
+        let Some(func) = self.env.get(name) else {
+            bail!(RuntimeError::NameError(format!(
+                "identifier not found: {}",
+                name
+            )));
+        };
+        self.evaluator.call(name, func, args.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        thread,
+    };
+
+    use anyhow::bail;
+
+    use crate::{
+        evaluator::{EvalError, RuntimeError, Traceback},
+        object::Object,
+        DEFAULT_MAX_CALL_DEPTH,
+    };
+
+    use super::{Interpreter, ParseError};
+
+    #[test]
+    fn test_globals_persist_between_evaluations() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval_str("let a = 5;").unwrap();
+        interpreter.eval_str("let b = a * 2;").unwrap();
+
+        assert_eq!(interpreter.get_global("b"), Some(Object::Integer(10)));
+        assert_eq!(interpreter.eval_str("a + b").unwrap(), Object::Integer(15));
+
+        interpreter.reset();
+        assert_eq!(interpreter.get_global("a"), None);
+        assert!(interpreter.globals().is_empty());
+    }
+
+    #[test]
+    fn test_empty_programs() {
+        let mut interpreter = Interpreter::new();
+        for source in ["", "\n\n", "  // nothing to see\n// here"] {
+            assert_eq!(
+                interpreter.eval_str(source).unwrap(),
+                Object::Null,
+                "{:?}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_default_call_depth() {
+        // runs on the test thread, far smaller than the stack this takes in a debug build
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval_str("let depth = fn(n) { if (n == 0) { 0 } else { 1 + depth(n - 1) } };")
+            .unwrap();
+
+        let limit = DEFAULT_MAX_CALL_DEPTH as i64;
+        let reached = interpreter
+            .call_function("depth", &[Object::Integer(limit)])
+            .unwrap();
+        assert_eq!(reached, Object::Integer(limit));
+
+        let err = interpreter
+            .call_function("depth", &[Object::Integer(limit + 1)])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<EvalError>(),
+            Some(&EvalError::MaxCallDepthExceeded {
+                function: "depth".to_string(),
+                limit: DEFAULT_MAX_CALL_DEPTH,
+            })
+        );
+    }
+
+    #[test]
+    fn test_tracebacks() {
+        let prelude = "let add = fn(a, b) { a + b };\nlet check = fn(x) { add(x, 1) * 1 };";
+        let tests = vec![
+            (
+                "check(true)",
+                "type mismatch: BOOLEAN + INTEGER\n  \
+                 in add, called at line 2\n  \
+                 in check, called at line 1",
+            ),
+            // functions keep the name they were first bound to
+            (
+                "let plus = add;\n\nplus(true, 1) * 1",
+                "type mismatch: BOOLEAN + INTEGER\n  in add, called at line 3",
+            ),
+            (
+                "fn(f) { f(1) * 1 }(fn(x) { x + true })",
+                "type mismatch: INTEGER + BOOLEAN\n  \
+                 in f, called at line 1\n  \
+                 in anonymous function, called at line 1",
+            ),
+            // tail calls replace the frame of their caller
+            (
+                "let relay = fn(x) {\n  add(x, true)\n};\nrelay(1)",
+                "type mismatch: INTEGER + BOOLEAN\n  in add, called at line 2",
+            ),
+            // errors outside of functions have no calls to trace
+            ("true + 1", "type mismatch: BOOLEAN + INTEGER"),
+        ];
+
+        for (input, expected) in tests {
+            let mut interpreter = Interpreter::new();
+            interpreter.eval_str(prelude).unwrap();
+            let err = interpreter.eval_str(input).unwrap_err();
+            assert_eq!(err.to_string(), expected, "{}", input);
+        }
+
+        let mut interpreter = Interpreter::new();
+        interpreter.eval_str(prelude).unwrap();
+        let err = interpreter
+            .call_function("check", &[Object::Boolean(true)])
+            .unwrap_err();
+        let traceback = err.downcast_ref::<Traceback>().unwrap();
+        let functions: Vec<_> = traceback.frames.iter().map(|f| &f.function).collect();
+        assert_eq!(functions, ["check", "add"]);
+        assert!(err.to_string().ends_with("in check, called by the host"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let mut interpreter = Interpreter::new();
+        let err = interpreter.eval_str("let = 5; let x 5;").unwrap_err();
+        let parse_error = err.downcast_ref::<ParseError>().unwrap();
+
+        assert!(!parse_error.errors.is_empty());
+        assert_eq!(interpreter.get_global("x"), None);
+    }
+
+    #[test]
+    fn test_call_function_errors() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_global("five", Object::Integer(5));
+
+        assert_eq!(
+            interpreter
+                .call_function("missing", &[])
+                .unwrap_err()
+                .to_string(),
+            "identifier not found: missing"
+        );
+        assert_eq!(
+            interpreter
+                .call_function("five", &[])
+                .unwrap_err()
+                .to_string(),
+            "not a function: INTEGER"
+        );
+    }
+
+    #[test]
+    fn test_host_functions() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+
+        let mut interpreter = Interpreter::new();
+        interpreter.register_function("add_one", move |args| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            match args {
+                [Object::Integer(n)] => Ok(Object::Integer(n + 1)),
+                [arg] => bail!("add_one expects an INTEGER, got {}", arg.type_val()),
+                _ => bail!("add_one expects 1 argument, got {}", args.len()),
+            }
+        });
+
+        let tests = vec![
+            ("add_one(1)", Object::Integer(2)),
+            ("add_one(add_one(1)) * 2", Object::Integer(6)),
+            // host function calls in tail position are applied directly
+            (
+                "let inc = fn(x) { add_one(x) }; inc(41);",
+                Object::Integer(42),
+            ),
+            ("let f = add_one; f(0);", Object::Integer(1)),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(interpreter.eval_str(input).unwrap(), expected, "{}", input);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+
+        assert_eq!(
+            interpreter
+                .eval_str("add_one(true)")
+                .unwrap_err()
+                .to_string(),
+            "add_one expects an INTEGER, got BOOLEAN"
+        );
+        assert_eq!(
+            interpreter
+                .call_function("add_one", &[Object::Integer(9)])
+                .unwrap(),
+            Object::Integer(10)
+        );
+        assert_eq!(
+            interpreter.get_global("add_one").unwrap().type_val(),
+            "HOST_FUNCTION"
+        );
+    }
+
+    #[test]
+    fn test_error_kinds_of_host_functions() {
+        let mut interpreter = Interpreter::new();
+        interpreter.register_function("typed", |args| {
+            bail!(RuntimeError::TypeError(format!(
+                "typed expects nothing, got {} arguments",
+                args.len()
+            )))
+        });
+        // the kind does not depend on the wording of the message
+        interpreter.register_function("untyped", |_| bail!("type mismatch in the host"));
+
+        let tests = vec![
+            ("typed(1)", "TypeError"),
+            ("untyped()", "RuntimeError"),
+            ("missing()", "NameError"),
+        ];
+        for (input, expected) in tests {
+            let source = format!("try {{ {} }} catch (e) {{ error_kind(e) }}", input);
+            assert_eq!(
+                interpreter.eval_str(&source).unwrap(),
+                Object::String(expected.to_string()),
+                "{}",
+                input
+            );
+        }
+    }
+
+    fn assert_send<T: Send>() {}
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_thread_safety() {
+        assert_send::<Interpreter>();
+        assert_send_sync::<Object>();
+    }
+
+    #[test]
+    fn test_concurrent_evaluation() {
+        let scripts: Vec<(String, i64)> = (0..32)
+            .map(|n| {
+                let script = format!(
+                    "let sum = fn(n, acc) {{ if (n == 0) {{ acc }} else {{ sum(n - 1, acc + n) }} }}; sum({}, 0);",
+                    n * 10
+                );
+                (script, (0..=n * 10).sum())
+            })
+            .collect();
+        let queue = Mutex::new(scripts.into_iter());
+
+        // a small pool of workers, each with its own interpreter, draining a shared queue
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let mut interpreter = Interpreter::new();
+                    while let Some((script, expected)) = queue.lock().unwrap().next() {
+                        assert_eq!(
+                            interpreter.eval_str(&script).unwrap(),
+                            Object::Integer(expected),
+                            "{}",
+                            script
+                        );
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn test_values_shared_between_threads() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval_str("let base = 100; let add_base = fn(x) { x + base };")
+            .unwrap();
+        let add_base = interpreter.get_global("add_base").unwrap();
+
+        // the closure and its captured scope are read from several threads at once
+        let results: Vec<_> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|n| {
+                    let add_base = &add_base;
+                    scope.spawn(move || {
+                        let mut interpreter = Interpreter::new();
+                        interpreter.set_global("add_base", add_base.clone());
+                        interpreter.eval_str(&format!("add_base({})", n)).unwrap()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert_eq!(results, (100..104).map(Object::Integer).collect::<Vec<_>>());
+
+        // a whole interpreter, globals included, can move to another thread
+        let result = thread::spawn(move || interpreter.eval_str("add_base(1)").unwrap())
+            .join()
+            .unwrap();
+        assert_eq!(result, Object::Integer(101));
+    }
+}
@@ -1,10 +1,13 @@
-use crate::token::{Token, TokenKind};
+use crate::token::{lookup_keyword, Comment, Span, Token, TokenKind};
 
 pub struct Lexer<'a> {
     input: &'a str,
+    // byte offsets of the current and the next char
     pub position: usize,
     read_position: usize,
     ch: char,
+    unterminated_string: bool,
+    comments: Vec<Comment>,
 }
 
 impl<'a> Lexer<'a> {
@@ -14,39 +17,29 @@ impl<'a> Lexer<'a> {
             position: 0,
             read_position: 0,
             ch: '\0',
+            unterminated_string: false,
+            comments: Vec::new(),
         };
         l.read_char();
         l
     }
 
     fn read_char(&mut self) {
-        if self.read_position >= self.input.len() {
-            self.ch = '\0';
-        } else {
-            self.ch = self
-                .input
-                .chars()
-                .nth(self.read_position)
-                .expect("char bound already checked")
-        }
+        self.ch = self.peak_char();
         self.position = self.read_position;
-        self.read_position += 1;
+        self.read_position += self.ch.len_utf8();
     }
 
-    fn peak_char(&mut self) -> char {
-        if self.read_position >= self.input.len() {
-            '\0'
-        } else {
-            self.input
-                .chars()
-                .nth(self.read_position)
-                .expect("char bound already checked")
-        }
+    fn peak_char(&self) -> char {
+        self.input
+            .get(self.read_position..)
+            .and_then(|rest| rest.chars().next())
+            .unwrap_or('\0')
     }
 
     fn read_itentifier(&mut self) -> &'a str {
         let pos = self.position;
-        while self.ch.is_alphabetic() {
+        while is_letter(self.ch) {
             self.read_char()
         }
         &self.input[pos..self.position]
@@ -54,7 +47,7 @@ impl<'a> Lexer<'a> {
 
     fn read_number(&mut self) -> &'a str {
         let pos = self.position;
-        while self.ch.is_numeric() {
+        while self.ch.is_ascii_digit() {
             self.read_char()
         }
         &self.input[pos..self.position]
@@ -66,119 +59,168 @@ impl<'a> Lexer<'a> {
         while self.ch != '"' && self.ch != '\0' {
             self.read_char()
         }
-        &self.input[pos..self.position]
+        self.unterminated_string = self.ch == '\0';
+        &self.input[pos..self.position.min(self.input.len())]
     }
 
-    fn consume_whitespace(&mut self) {
-        while self.ch == ' ' || self.ch == '\t' || self.ch == '\n' || self.ch == '\r' {
+    fn read_comment(&mut self) {
+        let start = self.position;
+        while self.ch != '\n' && self.ch != '\0' {
             self.read_char();
         }
+        let end = self.position.min(self.input.len());
+        self.comments.push(Comment {
+            text: self.input[start + 2..end].trim_end().to_string(),
+            span: Span { start, end },
+        });
     }
 
-    fn token_from_pos(&self, kind: TokenKind) -> Token<'a> {
-        Token {
-            kind,
-            val: &self.input[self.position..self.read_position],
+    fn consume_whitespace(&mut self) {
+        loop {
+            match self.ch {
+                ' ' | '\t' | '\n' | '\r' => self.read_char(),
+                '/' if self.peak_char() == '/' => self.read_comment(),
+                _ => break,
+            }
         }
     }
 
+    pub fn input(&self) -> &'a str {
+        self.input
+    }
+
+    /// The comments read so far, in source order.
+    pub fn take_comments(&mut self) -> Vec<Comment> {
+        std::mem::take(&mut self.comments)
+    }
+
     pub fn next_token(&mut self) -> Option<Token<'a>> {
         self.consume_whitespace();
 
         let ch = self.ch;
-        let token = match ch {
+        let start = self.position;
+        let kind = match ch {
             '=' => match self.peak_char() {
                 '=' => {
                     self.read_char();
-                    self.token_from_pos(TokenKind::Eq)
+                    TokenKind::Eq
                 }
-                _ => self.token_from_pos(TokenKind::Assign),
+                '>' => {
+                    self.read_char();
+                    TokenKind::Arrow
+                }
+                _ => TokenKind::Assign,
             },
-            '+' => self.token_from_pos(TokenKind::Plus),
-            '-' => self.token_from_pos(TokenKind::Minus),
+            '+' => TokenKind::Plus,
+            '-' => TokenKind::Minus,
             '!' => match self.peak_char() {
                 '=' => {
                     self.read_char();
-                    self.token_from_pos(TokenKind::NotEq)
+                    TokenKind::NotEq
                 }
-                _ => self.token_from_pos(TokenKind::Bang),
-            },
-            '*' => self.token_from_pos(TokenKind::Asterisk),
-            '/' => self.token_from_pos(TokenKind::Slash),
-            '<' => self.token_from_pos(TokenKind::Lt),
-            '>' => self.token_from_pos(TokenKind::Gt),
-            ';' => self.token_from_pos(TokenKind::Semicolon),
-            '(' => self.token_from_pos(TokenKind::Lparen),
-            ')' => self.token_from_pos(TokenKind::Rparen),
-            ',' => self.token_from_pos(TokenKind::Comma),
-            '{' => self.token_from_pos(TokenKind::Lbrace),
-            '}' => self.token_from_pos(TokenKind::Rbrace),
-            '\0' => Token {
-                kind: TokenKind::Eof,
-                val: "",
+                _ => TokenKind::Bang,
             },
+            '?' => TokenKind::Question,
+            '*' => TokenKind::Asterisk,
+            '/' => TokenKind::Slash,
+            '<' => TokenKind::Lt,
+            '>' => TokenKind::Gt,
+            ';' => TokenKind::Semicolon,
+            '(' => TokenKind::Lparen,
+            ')' => TokenKind::Rparen,
+            ',' => TokenKind::Comma,
+            '{' => TokenKind::Lbrace,
+            '}' => TokenKind::Rbrace,
+            '[' => TokenKind::Lbracket,
+            ']' => TokenKind::Rbracket,
+            ':' => TokenKind::Colon,
+            '.' if self.input[self.position..].starts_with("...") => {
+                self.read_char();
+                self.read_char();
+                TokenKind::Ellipsis
+            }
+            '\0' => return None,
             '"' => {
                 let val = self.read_string();
-                Token {
+                self.read_char();
+                let end = self.position.min(self.input.len());
+                return Some(Token {
                     kind: TokenKind::String,
-                    val
-                }
-            },
+                    val,
+                    span: Span { start, end },
+                });
+            }
             _ => {
-                if ch.is_alphabetic() {
+                let (kind, val) = if is_letter(ch) {
                     let ident = self.read_itentifier();
-                    let tok = match ident {
-                        "let" => Token {
-                            kind: TokenKind::Let,
-                            val: ident,
-                        },
-                        "fn" => Token {
-                            kind: TokenKind::Function,
-                            val: ident,
-                        },
-                        "if" => Token {
-                            kind: TokenKind::If,
-                            val: ident,
-                        },
-                        "else" => Token {
-                            kind: TokenKind::Else,
-                            val: ident,
-                        },
-                        "true" => Token {
-                            kind: TokenKind::True,
-                            val: ident,
-                        },
-                        "false" => Token {
-                            kind: TokenKind::False,
-                            val: ident,
-                        },
-                        "return" => Token {
-                            kind: TokenKind::Return,
-                            val: ident,
-                        },
-                        _ => Token {
-                            kind: TokenKind::Ident,
-                            val: ident,
-                        },
-                    };
-                    return Some(tok);
-                } else if ch.is_numeric() {
-                    let n = self.read_number();
-                    return Some(Token {
-                        kind: TokenKind::Int,
-                        val: n,
-                    });
+                    (lookup_keyword(ident).unwrap_or(TokenKind::Ident), ident)
+                } else if ch.is_ascii_digit() {
+                    (TokenKind::Int, self.read_number())
                 } else {
-                    self.token_from_pos(TokenKind::Illegal)
-                }
+                    self.read_char();
+                    (TokenKind::Illegal, &self.input[start..self.position])
+                };
+                return Some(Token {
+                    kind,
+                    val,
+                    span: Span {
+                        start,
+                        end: self.position,
+                    },
+                });
             }
         };
         self.read_char();
+        Some(Token {
+            kind,
+            val: &self.input[start..self.position],
+            span: Span {
+                start,
+                end: self.position,
+            },
+        })
+    }
+}
+
+/// Whether `input` stops in the middle of a statement: inside an unclosed paren, brace or
+/// string, or right after an operator. The REPL keeps reading lines until it is not.
+pub fn is_incomplete(input: &str) -> bool {
+    let mut lexer = Lexer::new(input);
+    let mut depth = 0;
+    let mut last = None;
+    while let Some(token) = lexer.next_token() {
         match token.kind {
-            TokenKind::Eof => None,
-            _ => Some(token),
+            TokenKind::Lparen | TokenKind::Lbrace | TokenKind::Lbracket => depth += 1,
+            TokenKind::Rparen | TokenKind::Rbrace | TokenKind::Rbracket => depth -= 1,
+            _ => {}
         }
+        last = Some(token.kind);
+    }
+    if lexer.unterminated_string || depth > 0 {
+        return true;
     }
+    matches!(
+        last,
+        Some(
+            TokenKind::Assign
+                | TokenKind::Plus
+                | TokenKind::Minus
+                | TokenKind::Bang
+                | TokenKind::Asterisk
+                | TokenKind::Slash
+                | TokenKind::Lt
+                | TokenKind::Gt
+                | TokenKind::Eq
+                | TokenKind::NotEq
+                | TokenKind::Comma
+                | TokenKind::Colon
+                | TokenKind::Arrow
+        )
+    )
+}
+
+fn is_letter(ch: char) -> bool {
+    ch.is_alphabetic() || ch == '_'
 }
 
 impl<'a> Iterator for Lexer<'a> {
@@ -191,64 +233,149 @@ impl<'a> Iterator for Lexer<'a> {
 
 #[cfg(test)]
 mod tests {
-    use crate::token::{Token, TokenKind};
+    use crate::token::{Comment, Span, TokenKind};
 
-    use super::Lexer;
+    use super::{is_incomplete, Lexer};
 
     #[test]
     fn next_token() {
         let input = "let five = 5;
         \"foobar\";
         \"foo bar\";
+        lookup_user;
 ";
         let tokens = vec![
-            Token {
-                kind: TokenKind::Let,
-                val: "let",
-            },
-            Token {
-                kind: TokenKind::Ident,
-                val: "five",
-            },
-            Token {
-                kind: TokenKind::Assign,
-                val: "=",
-            },
-            Token {
-                kind: TokenKind::Int,
-                val: "5",
-            },
-            Token {
-                kind: TokenKind::Semicolon,
-                val: ";",
-            },
-            Token {
-                kind: TokenKind::String,
-                val: "foobar",
-            },
-            Token {
-                kind: TokenKind::Semicolon,
-                val: ";",
-            },
-            Token {
-                kind: TokenKind::String,
-                val: "foo bar",
-            },
-            Token {
-                kind: TokenKind::Semicolon,
-                val: ";",
-            },
+            (TokenKind::Let, "let"),
+            (TokenKind::Ident, "five"),
+            (TokenKind::Assign, "="),
+            (TokenKind::Int, "5"),
+            (TokenKind::Semicolon, ";"),
+            (TokenKind::String, "foobar"),
+            (TokenKind::Semicolon, ";"),
+            (TokenKind::String, "foo bar"),
+            (TokenKind::Semicolon, ";"),
+            (TokenKind::Ident, "lookup_user"),
+            (TokenKind::Semicolon, ";"),
         ];
 
         let mut lexer = Lexer::new(input);
-        for (index, tt) in tokens.into_iter().enumerate() {
+        for (index, (kind, val)) in tokens.into_iter().enumerate() {
             let token = lexer.next_token().unwrap();
 
             assert_eq!(
-                tt, token,
+                (&kind, val),
+                (&token.kind, token.val),
                 "index {}, test {:?}, algo {:?}",
-                index, tt, token
+                index,
+                (&kind, val),
+                token
+            );
+        }
+        assert_eq!(lexer.next_token(), None);
+    }
+
+    #[test]
+    fn test_spans_and_comments() {
+        let input = "// header\nlet é = a == \"ö\"; // trailing\n  //   indented  \nx != 1";
+        let mut lexer = Lexer::new(input);
+        let tokens: Vec<_> = lexer.by_ref().collect();
+
+        let expected = vec![
+            (TokenKind::Let, "let", 10..13),
+            (TokenKind::Ident, "é", 14..16),
+            (TokenKind::Assign, "=", 17..18),
+            (TokenKind::Ident, "a", 19..20),
+            (TokenKind::Eq, "==", 21..23),
+            (TokenKind::String, "ö", 24..28),
+            (TokenKind::Semicolon, ";", 28..29),
+            (TokenKind::Ident, "x", 60..61),
+            (TokenKind::NotEq, "!=", 62..64),
+            (TokenKind::Int, "1", 65..66),
+        ];
+        assert_eq!(tokens.len(), expected.len());
+        for (token, (kind, val, range)) in tokens.iter().zip(expected) {
+            assert_eq!(token.kind, kind);
+            assert_eq!(token.val, val);
+            assert_eq!((token.span.start..token.span.end), range, "{:?}", token);
+            assert_eq!(
+                &input[range],
+                if kind == TokenKind::String {
+                    "\"ö\""
+                } else {
+                    val
+                }
             );
         }
+
+        assert_eq!(
+            lexer.take_comments(),
+            vec![
+                Comment {
+                    text: " header".to_string(),
+                    span: Span { start: 0, end: 9 },
+                },
+                Comment {
+                    text: " trailing".to_string(),
+                    span: Span { start: 30, end: 41 },
+                },
+                Comment {
+                    text: "   indented".to_string(),
+                    span: Span { start: 44, end: 59 },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pattern_tokens() {
+        let tokens: Vec<_> = Lexer::new("match [a, ...r] => {k: v} ..")
+            .map(|token| (token.kind, token.val))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::Match, "match"),
+                (TokenKind::Lbracket, "["),
+                (TokenKind::Ident, "a"),
+                (TokenKind::Comma, ","),
+                (TokenKind::Ellipsis, "..."),
+                (TokenKind::Ident, "r"),
+                (TokenKind::Rbracket, "]"),
+                (TokenKind::Arrow, "=>"),
+                (TokenKind::Lbrace, "{"),
+                (TokenKind::Ident, "k"),
+                (TokenKind::Colon, ":"),
+                (TokenKind::Ident, "v"),
+                (TokenKind::Rbrace, "}"),
+                (TokenKind::Illegal, "."),
+                (TokenKind::Illegal, "."),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_incomplete() {
+        let tests = vec![
+            ("", false),
+            ("let x = 5;", false),
+            ("let add = fn(x, y) {", true),
+            ("let add = fn(x, y) {\n x + y\n};", false),
+            ("add(1,", true),
+            ("add(1, 2)", false),
+            ("if (x > 1) { x } else {", true),
+            ("\"unterminated", true),
+            ("\"multi\nline\"", false),
+            ("let x =", true),
+            ("1 +", true),
+            ("x ==", true),
+            ("1 + 2)", false),
+            ("}", false),
+            ("match (x) { [a,", true),
+            ("match (x) { 1 =>", true),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(is_incomplete(input), expected, "{:?}", input);
+        }
     }
 }
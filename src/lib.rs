@@ -4,5 +4,22 @@ mod evaluator;
 pub mod parser;
 pub mod repl;
 pub mod token;
-mod object;
+pub mod object;
 mod environment;
+mod builtins;
+pub mod convert;
+pub mod dap;
+pub mod debugger;
+pub mod diagnostic;
+pub mod ffi;
+pub mod formatter;
+pub mod interpreter;
+pub mod line_editor;
+pub mod lint;
+pub mod lsp;
+pub mod shared_io;
+
+pub use evaluator::{
+    BudgetLimit, CancellationToken, EvalBudget, EvalError, Frame, RuntimeError, Traceback,
+    DEFAULT_MAX_CALL_DEPTH, MAX_NESTING_DEPTH,
+};
@@ -0,0 +1,159 @@
+use std::path::PathBuf;
+
+use anyhow::{Context as _, Result};
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    history::FileHistory, validate::Validator, Context, Editor, Helper,
+};
+
+use crate::{builtins::Builtin, token::KEYWORDS};
+
+/// Default location of the REPL history, `None` when there is no home directory.
+pub fn default_history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".monkey_history"))
+}
+
+/// The word being typed at `pos` and the names it could be completed to, from the
+/// keywords, the builtins and `identifiers`.
+fn complete_word(line: &str, pos: usize, identifiers: &[String]) -> (usize, Vec<String>) {
+    let start = line[..pos]
+        .rfind(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+        .map_or(0, |idx| idx + 1);
+    let word = &line[start..pos];
+    if word.is_empty() {
+        return (pos, Vec::new());
+    }
+
+    let mut candidates: Vec<String> = KEYWORDS
+        .iter()
+        .map(|(keyword, _)| *keyword)
+        .chain(Builtin::ALL.iter().map(Builtin::name))
+        .map(str::to_string)
+        .chain(identifiers.iter().cloned())
+        .filter(|name| name.starts_with(word))
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    (start, candidates)
+}
+
+struct MonkeyHelper {
+    identifiers: Vec<String>,
+}
+
+impl Completer for MonkeyHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete_word(line, pos, &self.identifiers))
+    }
+}
+
+impl Hinter for MonkeyHelper {
+    type Hint = String;
+}
+
+impl Highlighter for MonkeyHelper {}
+
+impl Validator for MonkeyHelper {}
+
+impl Helper for MonkeyHelper {}
+
+pub enum ReadLine {
+    Line(String),
+    /// Ctrl-C, the input typed so far should be discarded.
+    Interrupted,
+    /// Ctrl-D on an empty line.
+    Eof,
+}
+
+/// Terminal line editing for the REPL, with history kept in a file across sessions.
+pub struct LineEditor {
+    editor: Editor<MonkeyHelper, FileHistory>,
+    history: Option<PathBuf>,
+}
+
+impl LineEditor {
+    pub fn new(history: Option<PathBuf>) -> Result<Self> {
+        let mut editor = Editor::new().context("unable to start line editor")?;
+        editor.set_helper(Some(MonkeyHelper {
+            identifiers: Vec::new(),
+        }));
+        if let Some(path) = &history {
+            // there is no history yet on the first run
+            let _ = editor.load_history(path);
+        }
+        Ok(Self { editor, history })
+    }
+
+    /// Names offered by tab completion besides keywords and builtins.
+    pub fn set_identifiers(&mut self, identifiers: Vec<String>) {
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.identifiers = identifiers;
+        }
+    }
+
+    /// Reads a line, without its newline.
+    pub fn read_line(&mut self, prompt: &str) -> Result<ReadLine> {
+        match self.editor.readline(prompt) {
+            Ok(line) => Ok(ReadLine::Line(line)),
+            Err(ReadlineError::Interrupted) => Ok(ReadLine::Interrupted),
+            Err(ReadlineError::Eof) => Ok(ReadLine::Eof),
+            Err(err) => Err(err).context("failed to read line"),
+        }
+    }
+
+    pub fn add_history(&mut self, entry: &str) -> Result<()> {
+        self.editor
+            .add_history_entry(entry)
+            .context("unable to update history")?;
+        if let Some(path) = &self.history {
+            self.editor
+                .save_history(path)
+                .with_context(|| format!("unable to save history to {}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::complete_word;
+
+    #[test]
+    fn test_complete_word() {
+        let identifiers = vec!["lookup_user".to_string(), "letters".to_string()];
+        let tests = vec![
+            ("le", 2, (0, vec!["let", "letters"])),
+            ("let x = lo", 10, (8, vec!["lookup_user"])),
+            ("pu", 2, (0, vec!["puts"])),
+            ("f(re", 4, (2, vec!["return"])),
+            ("let x = ", 8, (8, vec![])),
+            ("xyz", 3, (0, vec![])),
+            // only the text before the cursor is completed
+            ("fal + 1", 3, (0, vec!["false"])),
+        ];
+
+        for (line, pos, (start, candidates)) in tests {
+            assert_eq!(
+                complete_word(line, pos, &identifiers),
+                (
+                    start,
+                    candidates
+                        .into_iter()
+                        .map(str::to_string)
+                        .collect::<Vec<_>>()
+                ),
+                "{:?}",
+                line
+            );
+        }
+    }
+}
@@ -0,0 +1,575 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{
+        ArmBody, CallableExpression, Expression, FunctionLiteral, Operator, Pattern, Program,
+        Statement,
+    },
+    builtins::Builtin,
+    diagnostic::{Diagnostic, Severity},
+    lexer::Lexer,
+    parser::Parser,
+    token::Span,
+};
+
+/// A check made by [`lint_source`], named in diagnostics by its `code`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    UnusedLet,
+    UnusedParameter,
+    Shadowing,
+    UnreachableCode,
+    ConstantCondition,
+    MismatchedComparison,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 6] = [
+        Rule::UnusedLet,
+        Rule::UnusedParameter,
+        Rule::Shadowing,
+        Rule::UnreachableCode,
+        Rule::ConstantCondition,
+        Rule::MismatchedComparison,
+    ];
+
+    pub fn lookup(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|rule| rule.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::UnusedLet => "unused-let",
+            Rule::UnusedParameter => "unused-parameter",
+            Rule::Shadowing => "shadowing",
+            Rule::UnreachableCode => "unreachable-code",
+            Rule::ConstantCondition => "constant-condition",
+            Rule::MismatchedComparison => "mismatched-comparison",
+        }
+    }
+
+    pub fn default_severity(&self) -> Severity {
+        match self {
+            // shadowing a name is often deliberate
+            Rule::Shadowing => Severity::Info,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+/// Severity each rule reports with, rules set to `None` are not checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintConfig {
+    severities: HashMap<Rule, Option<Severity>>,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            severities: Rule::ALL
+                .into_iter()
+                .map(|rule| (rule, Some(rule.default_severity())))
+                .collect(),
+        }
+    }
+}
+
+impl LintConfig {
+    pub fn with_rule(mut self, rule: Rule, severity: Option<Severity>) -> Self {
+        self.severities.insert(rule, severity);
+        self
+    }
+
+    pub fn severity(&self, rule: Rule) -> Option<Severity> {
+        self.severities.get(&rule).copied().flatten()
+    }
+}
+
+/// Parses and lints `source`, returning the parse errors instead when it does not
+/// parse. Bindings whose name starts with `_` are never reported as unused.
+///
+/// ```
+/// use thorsten_interpreter::lint::{lint_source, LintConfig};
+///
+/// let diagnostics = lint_source("let f = fn(x) { 1 };", &LintConfig::default());
+/// let messages: Vec<_> = diagnostics.iter().map(|d| d.to_string()).collect();
+/// assert_eq!(messages, ["unused binding `f`", "unused parameter `x`"]);
+/// ```
+pub fn lint_source(source: &str, config: &LintConfig) -> Vec<Diagnostic> {
+    let program = Parser::new(Lexer::new(source)).parse_program();
+    if !program.errors.is_empty() {
+        return program.errors;
+    }
+    lint(&program, config)
+}
+
+pub(crate) fn lint(program: &Program, config: &LintConfig) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        config,
+        scopes: vec![Scope::default()],
+        diagnostics: Vec::new(),
+    };
+    linter.statements(&program.statments);
+    linter.pop_scope();
+    linter.diagnostics.sort_by_key(|d| d.span.start);
+    linter.diagnostics
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BindingKind {
+    Let,
+    Parameter,
+    // errors bound by `catch`, which may be ignored
+    Catch,
+    // names bound by the pattern of a match arm
+    Arm,
+}
+
+struct Binding {
+    name: String,
+    span: Span,
+    kind: BindingKind,
+    used: bool,
+}
+
+// Blocks share the scope of the function around them, as in the evaluator.
+#[derive(Default)]
+struct Scope {
+    bindings: Vec<Binding>,
+    // names not found when referenced directly in this scope
+    unresolved: HashSet<String>,
+    // names not found from functions created in this scope, which may be bound later
+    // and still be visible when the functions are called
+    pending: HashSet<String>,
+}
+
+struct Linter<'a> {
+    config: &'a LintConfig,
+    scopes: Vec<Scope>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, rule: Rule, message: String, span: Span) {
+        if let Some(severity) = self.config.severity(rule) {
+            self.diagnostics.push(Diagnostic {
+                severity,
+                message,
+                span,
+                code: Some(rule.name()),
+            });
+        }
+    }
+
+    fn current_scope(&mut self) -> &mut Scope {
+        self.scopes
+            .last_mut()
+            .expect("the program scope is never popped early")
+    }
+
+    fn declare(&mut self, name: &str, span: Span, kind: BindingKind) {
+        // `catch (e)` and the names of match arms are usually repeated in a function, each
+        // one a binding of its own
+        let shadowed = self.scopes.iter().any(|scope| {
+            scope.bindings.iter().any(|binding| {
+                binding.name == name
+                    && !(kind == binding.kind
+                        && matches!(kind, BindingKind::Catch | BindingKind::Arm))
+            })
+        });
+        if shadowed {
+            self.report(
+                Rule::Shadowing,
+                format!("`{}` shadows an earlier binding", name),
+                span,
+            );
+        } else if Builtin::lookup(name).is_some() {
+            self.report(
+                Rule::Shadowing,
+                format!("`{}` shadows the builtin function", name),
+                span,
+            );
+        }
+
+        let scope = self.current_scope();
+        let used = scope.pending.remove(name);
+        scope.bindings.push(Binding {
+            name: name.to_string(),
+            span,
+            kind,
+            used,
+        });
+    }
+
+    fn resolve(&mut self, name: &str) {
+        let binding = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.bindings.iter_mut().rev().find(|b| b.name == name));
+        match binding {
+            Some(binding) => binding.used = true,
+            None => {
+                self.current_scope().unresolved.insert(name.to_string());
+            }
+        }
+    }
+
+    fn pop_scope(&mut self) {
+        let scope = self
+            .scopes
+            .pop()
+            .expect("scopes are pushed and popped in pairs");
+        if let Some(outer) = self.scopes.last_mut() {
+            outer.pending.extend(scope.unresolved);
+            outer.pending.extend(scope.pending);
+        }
+
+        for binding in scope.bindings {
+            if binding.used || binding.name.starts_with('_') {
+                continue;
+            }
+            match binding.kind {
+                BindingKind::Let | BindingKind::Arm => self.report(
+                    Rule::UnusedLet,
+                    format!("unused binding `{}`", binding.name),
+                    binding.span,
+                ),
+                BindingKind::Parameter => self.report(
+                    Rule::UnusedParameter,
+                    format!("unused parameter `{}`", binding.name),
+                    binding.span,
+                ),
+                BindingKind::Catch => {}
+            }
+        }
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        let exit = statements
+            .iter()
+            .position(|statement| matches!(statement, Statement::Return(_) | Statement::Throw(_)));
+        if let Some(idx) = exit.filter(|idx| idx + 1 < statements.len()) {
+            let keyword = match statements[idx] {
+                Statement::Throw(_) => "throw",
+                _ => "return",
+            };
+            let span = statements[idx + 1]
+                .span()
+                .to(statements[statements.len() - 1].span());
+            self.report(
+                Rule::UnreachableCode,
+                format!("unreachable statement after {}", keyword),
+                span,
+            );
+        }
+
+        // unreachable statements are still walked so the names they use count as used
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let(statement) => {
+                self.expression(&statement.value);
+                self.pattern(&statement.pattern, BindingKind::Let);
+            }
+            Statement::Return(statement) => self.expression(&statement.value),
+            Statement::Throw(statement) => self.expression(&statement.value),
+            Statement::Expression(statement) => self.expression(&statement.expression),
+            Statement::Block(block) => self.statements(&block.statements),
+        }
+    }
+
+    // Declares the names `pattern` binds, after the defaults it evaluates.
+    fn pattern(&mut self, pattern: &Pattern, kind: BindingKind) {
+        for default in pattern.defaults() {
+            self.expression(default);
+        }
+        for name in pattern.bindings() {
+            self.declare(&name.value, name.span, kind);
+        }
+    }
+
+    fn function(&mut self, literal: &FunctionLiteral) {
+        self.scopes.push(Scope::default());
+        for parameter in &literal.parameters {
+            self.declare(&parameter.value, parameter.span, BindingKind::Parameter);
+        }
+        self.statements(&literal.body.statements);
+        self.pop_scope();
+    }
+
+    fn expression(&mut self, exp: &Expression) {
+        match exp {
+            Expression::Callable(CallableExpression::Identifier(ident)) => {
+                self.resolve(&ident.value)
+            }
+            Expression::Callable(CallableExpression::FunctionLiteral(literal)) => {
+                self.function(literal)
+            }
+            Expression::IntegerLiteral(_)
+            | Expression::StringLiteral(_)
+            | Expression::BooleanLiteral(_) => {}
+            Expression::ArrayLiteral(literal) => {
+                for element in &literal.elements {
+                    self.expression(element);
+                }
+            }
+            Expression::HashLiteral(literal) => {
+                for (key, value) in &literal.pairs {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            Expression::Prefix(exp) => self.expression(&exp.right),
+            Expression::Propagate(exp) => self.expression(&exp.value),
+            Expression::Infix(infix) => {
+                self.comparison(&infix.operator, &infix.left, &infix.right, infix.span);
+                self.expression(&infix.left);
+                self.expression(&infix.right);
+            }
+            Expression::If(exp) => {
+                if let Some(truthy) = literal_truthiness(&exp.condition) {
+                    self.report(
+                        Rule::ConstantCondition,
+                        format!("condition is always {}", truthy),
+                        exp.condition.span(),
+                    );
+                }
+                self.expression(&exp.condition);
+                self.statements(&exp.consequence.statements);
+                if let Some(alternative) = &exp.alternative {
+                    self.statements(&alternative.statements);
+                }
+            }
+            Expression::Try(exp) => {
+                self.statements(&exp.body.statements);
+                if let Some(catch) = &exp.catch {
+                    self.declare(&catch.name.value, catch.name.span, BindingKind::Catch);
+                    self.statements(&catch.body.statements);
+                }
+                if let Some(finally) = &exp.finally {
+                    self.statements(&finally.statements);
+                }
+            }
+            Expression::Match(exp) => {
+                self.expression(&exp.value);
+                for arm in &exp.arms {
+                    self.pattern(&arm.pattern, BindingKind::Arm);
+                    if let Some(guard) = &arm.guard {
+                        self.expression(guard);
+                    }
+                    match &arm.body {
+                        ArmBody::Expression(exp) => self.expression(exp),
+                        ArmBody::Block(block) => self.statements(&block.statements),
+                    }
+                }
+            }
+            Expression::Call(call) => {
+                match &call.func {
+                    CallableExpression::Identifier(ident) => self.resolve(&ident.value),
+                    CallableExpression::FunctionLiteral(literal) => self.function(literal),
+                }
+                for argument in &call.arguments {
+                    self.expression(argument);
+                }
+            }
+        }
+    }
+
+    fn comparison(
+        &mut self,
+        operator: &Operator,
+        left: &Expression,
+        right: &Expression,
+        span: Span,
+    ) {
+        let (Some(left), Some(right)) = (literal_type(left), literal_type(right)) else {
+            return;
+        };
+        if left == right {
+            return;
+        }
+        let outcome = match operator {
+            Operator::Eq => "is always false",
+            Operator::NotEq => "is always true",
+            Operator::Lt | Operator::Gt => "fails with a type mismatch",
+            _ => return,
+        };
+        self.report(
+            Rule::MismatchedComparison,
+            format!("comparing {} with {} {}", left, right, outcome),
+            span,
+        );
+    }
+}
+
+// type of a literal, as `Object::type_val` names it
+fn literal_type(exp: &Expression) -> Option<&'static str> {
+    match exp {
+        Expression::IntegerLiteral(_) => Some("INTEGER"),
+        Expression::StringLiteral(_) => Some("STRING"),
+        Expression::BooleanLiteral(_) => Some("BOOLEAN"),
+        _ => None,
+    }
+}
+
+fn literal_truthiness(exp: &Expression) -> Option<bool> {
+    match exp {
+        Expression::IntegerLiteral(_) | Expression::StringLiteral(_) => Some(true),
+        Expression::BooleanLiteral(literal) => Some(literal.value),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lint_source, LintConfig, Rule};
+    use crate::diagnostic::Severity;
+
+    // (rule, message, source text of the span) for each diagnostic
+    fn lint(input: &str, config: &LintConfig) -> Vec<(String, String, String)> {
+        lint_source(input, config)
+            .into_iter()
+            .map(|d| {
+                (
+                    d.code.unwrap_or("parse").to_string(),
+                    d.message,
+                    input[d.span.start..d.span.end].to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        let tests = vec![
+            ("let x = 1; puts(x);", vec![]),
+            (
+                "let x = 1;",
+                vec![("unused-let", "unused binding `x`", "x")],
+            ),
+            ("let _x = 1; let f = fn(_y) { 1 }; f(2);", vec![]),
+            (
+                "let add = fn(a, b) { a }; add(1, 2);",
+                vec![("unused-parameter", "unused parameter `b`", "b")],
+            ),
+            // recursion and functions calling ones bound after them
+            (
+                "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } };
+                 let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } };
+                 puts(even(4));",
+                vec![],
+            ),
+            // blocks do not open a scope
+            ("if (x) { let y = 1; } puts(y);", vec![]),
+            (
+                "let x = 1; let f = fn(x) { x }; f(x);",
+                vec![("shadowing", "`x` shadows an earlier binding", "x")],
+            ),
+            (
+                "let x = 1; let x = x + 1; puts(x);",
+                vec![("shadowing", "`x` shadows an earlier binding", "x")],
+            ),
+            (
+                "let puts = fn(s) { s }; puts(1);",
+                vec![("shadowing", "`puts` shadows the builtin function", "puts")],
+            ),
+            (
+                "let f = fn(x) { return x; puts(x); x };\nf(1);",
+                vec![(
+                    "unreachable-code",
+                    "unreachable statement after return",
+                    "puts(x); x",
+                )],
+            ),
+            (
+                "let f = fn(x) { throw x; x };\nf(1);",
+                vec![("unreachable-code", "unreachable statement after throw", "x")],
+            ),
+            (
+                "match (x) { [a, b] => a, [a] => a, {name: n} => 1 }",
+                vec![
+                    ("unused-let", "unused binding `b`", "b"),
+                    ("unused-let", "unused binding `n`", "n"),
+                ],
+            ),
+            (
+                "let c = 1; let [a, b = c] = xs; puts(a);",
+                vec![("unused-let", "unused binding `b`", "b")],
+            ),
+            // catch bindings may go unused and reuse a name
+            (
+                "try { 1 } catch (e) { 2 }; try { 1 } catch (e) { e }",
+                vec![],
+            ),
+            (
+                "if (true) { 1 } else { 2 }; if (0) { 1 }; if (false) { 1 }",
+                vec![
+                    ("constant-condition", "condition is always true", "true"),
+                    ("constant-condition", "condition is always true", "0"),
+                    ("constant-condition", "condition is always false", "false"),
+                ],
+            ),
+            (
+                "1 == \"1\"; true != 1; \"a\" < 2; 1 == 2; x == 1",
+                vec![
+                    (
+                        "mismatched-comparison",
+                        "comparing INTEGER with STRING is always false",
+                        "1 == \"1\"",
+                    ),
+                    (
+                        "mismatched-comparison",
+                        "comparing BOOLEAN with INTEGER is always true",
+                        "true != 1",
+                    ),
+                    (
+                        "mismatched-comparison",
+                        "comparing STRING with INTEGER fails with a type mismatch",
+                        "\"a\" < 2",
+                    ),
+                ],
+            ),
+            (
+                "let x 1;",
+                vec![("parse", "expected `let <pattern> =`, got `1`", "x")],
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let expected: Vec<_> = expected
+                .into_iter()
+                .map(|(rule, message, text)| {
+                    (rule.to_string(), message.to_string(), text.to_string())
+                })
+                .collect();
+            assert_eq!(lint(input, &LintConfig::default()), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_config() {
+        let input = "let x = 1; let f = fn(x) { 1 };";
+        let config = LintConfig::default()
+            .with_rule(Rule::UnusedLet, None)
+            .with_rule(Rule::Shadowing, Some(Severity::Error));
+
+        let severities: Vec<_> = lint_source(input, &config)
+            .into_iter()
+            .map(|d| (d.code.unwrap(), d.severity))
+            .collect();
+        assert_eq!(
+            severities,
+            [
+                ("shadowing", Severity::Error),
+                ("unused-parameter", Severity::Warning)
+            ]
+        );
+        assert_eq!(Rule::lookup("unused-let"), Some(Rule::UnusedLet));
+        assert_eq!(Rule::lookup("unused"), None);
+    }
+}
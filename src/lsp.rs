@@ -0,0 +1,920 @@
+//! Language server for Monkey, speaking JSON-RPC over any reader and writer, as the
+//! `monkey-lsp` binary does over stdin and stdout.
+//!
+//! Documents are synchronized in full on every change. The server publishes the parse
+//! errors and lint diagnostics of each document, and answers hover, go-to-definition,
+//! document symbol and completion requests.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+};
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+
+use crate::{
+    ast::{ArmBody, CallableExpression, Expression, FunctionLiteral, Pattern, Program, Statement},
+    builtins::Builtin,
+    diagnostic::{Diagnostic, Severity},
+    lexer::Lexer,
+    lint::{lint, LintConfig},
+    parser::Parser,
+    token::{Span, KEYWORDS},
+};
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+// LSP enumerations
+const SYNC_FULL: i64 = 1;
+const SYMBOL_FUNCTION: i64 = 12;
+const SYMBOL_VARIABLE: i64 = 13;
+const COMPLETION_FUNCTION: i64 = 3;
+const COMPLETION_KEYWORD: i64 = 14;
+
+/// Reads the messages from `reader` and writes the responses to `writer` until the
+/// client sends `exit` or closes the input.
+pub fn run(mut reader: impl BufRead, mut writer: impl Write) -> Result<()> {
+    let mut server = LanguageServer::new();
+    while let Some(body) = read_message(&mut reader)? {
+        let responses = match serde_json::from_str::<Value>(&body) {
+            Ok(message) => server.handle(&message),
+            Err(err) => vec![error_response(Value::Null, PARSE_ERROR, err.to_string())],
+        };
+        for response in responses {
+            write_message(&mut writer, &response)?;
+        }
+        if server.exited() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Body of the next message, `None` at the end of the input.
+pub fn read_message(reader: &mut impl BufRead) -> Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        let read = reader
+            .read_line(&mut header)
+            .context("unable to read message header")?;
+        if read == 0 {
+            if length.is_some() {
+                bail!("input ended in a message header");
+            }
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = Some(
+                    value
+                        .trim()
+                        .parse::<usize>()
+                        .with_context(|| format!("invalid Content-Length: {}", value.trim()))?,
+                );
+            }
+        }
+    }
+
+    let Some(length) = length else {
+        bail!("message without Content-Length");
+    };
+    let mut body = vec![0; length];
+    reader
+        .read_exact(&mut body)
+        .context("unable to read message body")?;
+    String::from_utf8(body)
+        .map(Some)
+        .context("message body is not UTF-8")
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Value) -> Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+        .and_then(|_| writer.flush())
+        .context("unable to write message")
+}
+
+fn response(id: Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn error_response(id: Value, code: i64, message: String) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+/// LSP position of the byte `offset`, whose character counts UTF-16 code units.
+fn position(source: &str, offset: usize) -> Value {
+    let before = &source[..offset.min(source.len())];
+    let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].encode_utf16().count(),
+    })
+}
+
+fn range(source: &str, span: Span) -> Value {
+    json!({ "start": position(source, span.start), "end": position(source, span.end) })
+}
+
+/// Byte offset of an LSP position, clamped to the line and the source.
+fn offset(source: &str, position: &Value) -> Option<usize> {
+    let line = position.get("line")?.as_u64()? as usize;
+    let character = position.get("character")?.as_u64()? as usize;
+
+    let mut line_start = 0;
+    for _ in 0..line {
+        line_start += source[line_start..].find('\n')? + 1;
+    }
+    let text = source[line_start..].split('\n').next().unwrap_or("");
+    let mut units = 0;
+    for (idx, ch) in text.char_indices() {
+        if units >= character {
+            return Some(line_start + idx);
+        }
+        units += ch.len_utf16();
+    }
+    Some(line_start + text.len())
+}
+
+#[derive(Default)]
+pub struct LanguageServer {
+    documents: HashMap<String, String>,
+    lint_config: LintConfig,
+    shutdown: bool,
+    exited: bool,
+}
+
+impl LanguageServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_lint_config(mut self, lint_config: LintConfig) -> Self {
+        self.lint_config = lint_config;
+        self
+    }
+
+    /// Whether the client sent `exit`, after which the server should stop.
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Handles a request or notification, returning the messages to send back.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message.get("method").and_then(Value::as_str);
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        let Some(id) = message.get("id").cloned() else {
+            return match method {
+                Some(method) => self.notify(method, &params),
+                // responses to requests the server never makes
+                None => Vec::new(),
+            };
+        };
+        let Some(method) = method else {
+            return vec![error_response(
+                id,
+                INVALID_REQUEST,
+                "request without method".to_string(),
+            )];
+        };
+        if self.shutdown {
+            return vec![error_response(
+                id,
+                INVALID_REQUEST,
+                "server is shut down".to_string(),
+            )];
+        }
+
+        let result = match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": SYNC_FULL,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "documentSymbolProvider": true,
+                    "completionProvider": {},
+                },
+                "serverInfo": { "name": "monkey-lsp", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/hover" => self.hover(&params),
+            "textDocument/definition" => self.definition(&params),
+            "textDocument/documentSymbol" => self.document_symbols(&params),
+            "textDocument/completion" => Ok(completions()),
+            _ => {
+                return vec![error_response(
+                    id,
+                    METHOD_NOT_FOUND,
+                    format!("unknown method: {}", method),
+                )]
+            }
+        };
+        match result {
+            Ok(result) => vec![response(id, result)],
+            Err(err) => vec![error_response(id, INVALID_PARAMS, err.to_string())],
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().map(str::to_string);
+        match (method, uri) {
+            ("exit", _) => {
+                self.exited = true;
+                Vec::new()
+            }
+            ("textDocument/didOpen", Some(uri)) => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                self.documents.insert(uri.clone(), text.to_string());
+                vec![self.publish_diagnostics(&uri)]
+            }
+            ("textDocument/didChange", Some(uri)) => {
+                // with full sync the last change holds the whole document
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+                match text {
+                    Some(text) => {
+                        self.documents.insert(uri.clone(), text.to_string());
+                        vec![self.publish_diagnostics(&uri)]
+                    }
+                    None => Vec::new(),
+                }
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                self.documents.remove(&uri);
+                vec![notification(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri, "diagnostics": [] }),
+                )]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn publish_diagnostics(&self, uri: &str) -> Value {
+        let source = &self.documents[uri];
+        let program = parse(source);
+        let diagnostics = if program.errors.is_empty() {
+            lint(&program, &self.lint_config)
+        } else {
+            program.errors
+        };
+        let diagnostics: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| lsp_diagnostic(source, diagnostic))
+            .collect();
+        notification(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        )
+    }
+
+    // document and byte offset a position request is about
+    fn document_at<'a>(&'a self, params: &'a Value) -> Result<(&'a str, &'a str, usize)> {
+        let Some(uri) = params["textDocument"]["uri"].as_str() else {
+            bail!("missing textDocument.uri");
+        };
+        let Some(source) = self.documents.get(uri) else {
+            bail!("unknown document: {}", uri);
+        };
+        let Some(offset) = offset(source, &params["position"]) else {
+            bail!("invalid position");
+        };
+        Ok((uri, source, offset))
+    }
+
+    fn hover(&self, params: &Value) -> Result<Value> {
+        let (_, source, offset) = self.document_at(params)?;
+        let index = Index::new(&parse(source));
+        let Some(reference) = index.reference_at(offset) else {
+            return Ok(Value::Null);
+        };
+        let detail = match reference.definition {
+            Some(definition) => index.definitions[definition].detail.clone(),
+            None => match Builtin::lookup(&reference.name) {
+                Some(builtin) => format!("builtin {}", builtin.name()),
+                None => return Ok(Value::Null),
+            },
+        };
+        Ok(json!({
+            "contents": { "kind": "markdown", "value": format!("```monkey\n{}\n```", detail) },
+            "range": range(source, reference.span),
+        }))
+    }
+
+    fn definition(&self, params: &Value) -> Result<Value> {
+        let (uri, source, offset) = self.document_at(params)?;
+        let index = Index::new(&parse(source));
+        let definition = index
+            .reference_at(offset)
+            .and_then(|reference| reference.definition);
+        Ok(match definition {
+            Some(definition) => json!({
+                "uri": uri,
+                "range": range(source, index.definitions[definition].span),
+            }),
+            None => Value::Null,
+        })
+    }
+
+    fn document_symbols(&self, params: &Value) -> Result<Value> {
+        let Some(uri) = params["textDocument"]["uri"].as_str() else {
+            bail!("missing textDocument.uri");
+        };
+        let Some(source) = self.documents.get(uri) else {
+            bail!("unknown document: {}", uri);
+        };
+        Ok(Value::Array(symbols(source, &parse(source).statments)))
+    }
+}
+
+fn parse(source: &str) -> Program {
+    Parser::new(Lexer::new(source)).parse_program()
+}
+
+fn lsp_diagnostic(source: &str, diagnostic: &Diagnostic) -> Value {
+    let severity = match diagnostic.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Info => 3,
+    };
+    let mut value = json!({
+        "range": range(source, diagnostic.span),
+        "severity": severity,
+        "source": "monkey",
+        "message": diagnostic.message,
+    });
+    if let Some(code) = diagnostic.code {
+        value["code"] = json!(code);
+    }
+    value
+}
+
+fn completions() -> Value {
+    let keywords = KEYWORDS
+        .iter()
+        .map(|(keyword, _)| json!({ "label": keyword, "kind": COMPLETION_KEYWORD }));
+    let builtins = Builtin::ALL
+        .iter()
+        .map(|builtin| json!({ "label": builtin.name(), "kind": COMPLETION_FUNCTION }));
+    Value::Array(keywords.chain(builtins).collect())
+}
+
+// `let` bindings, with the ones inside a function body as children of the function
+fn symbols(source: &str, statements: &[Statement]) -> Vec<Value> {
+    let mut found = Vec::new();
+    for statement in statements {
+        match statement {
+            Statement::Let(statement) => {
+                let (kind, children) = match &statement.value {
+                    Expression::Callable(CallableExpression::FunctionLiteral(literal)) => {
+                        (SYMBOL_FUNCTION, symbols(source, &literal.body.statements))
+                    }
+                    _ => (SYMBOL_VARIABLE, Vec::new()),
+                };
+                let Some(name) = statement.name() else {
+                    // each name a destructuring `let` binds is a variable of its own
+                    for name in statement.pattern.bindings() {
+                        found.push(json!({
+                            "name": name.value,
+                            "kind": SYMBOL_VARIABLE,
+                            "range": range(source, statement.span),
+                            "selectionRange": range(source, name.span),
+                            "children": [],
+                        }));
+                    }
+                    continue;
+                };
+                found.push(json!({
+                    "name": name.value,
+                    "kind": kind,
+                    "range": range(source, statement.span),
+                    "selectionRange": range(source, name.span),
+                    "children": children,
+                }));
+            }
+            Statement::Block(block) => found.extend(symbols(source, &block.statements)),
+            Statement::Return(_) | Statement::Throw(_) | Statement::Expression(_) => {}
+        }
+    }
+    found
+}
+
+struct Definition {
+    span: Span,
+    // what hover shows
+    detail: String,
+    scope: usize,
+}
+
+struct Reference {
+    name: String,
+    span: Span,
+    definition: Option<usize>,
+}
+
+/// Every name in a program, with the binding it refers to. Bindings themselves are
+/// indexed as references to their own definition.
+struct Index {
+    definitions: Vec<Definition>,
+    references: Vec<Reference>,
+}
+
+impl Index {
+    fn new(program: &Program) -> Self {
+        let mut builder = IndexBuilder {
+            definitions: Vec::new(),
+            names: Vec::new(),
+            unresolved: Vec::new(),
+            references: Vec::new(),
+            scopes: vec![0],
+            next_scope: 1,
+        };
+        builder.statements(&program.statments);
+
+        // functions see the bindings made after them in the scopes around them, as
+        // those exist by the time they are called
+        let mut references = builder.references;
+        for (reference, scopes) in builder.unresolved {
+            let definition = scopes.iter().rev().find_map(|scope| {
+                let mut candidates =
+                    builder
+                        .definitions
+                        .iter()
+                        .enumerate()
+                        .filter(|(idx, definition)| {
+                            definition.scope == *scope && builder.names[*idx] == reference.name
+                        });
+                candidates.next().map(|(idx, _)| idx)
+            });
+            references.push(Reference {
+                definition,
+                ..reference
+            });
+        }
+        references.sort_by_key(|reference| reference.span.start);
+
+        Index {
+            definitions: builder.definitions,
+            references,
+        }
+    }
+
+    fn reference_at(&self, offset: usize) -> Option<&Reference> {
+        self.references
+            .iter()
+            .find(|reference| reference.span.start <= offset && offset <= reference.span.end)
+    }
+}
+
+struct IndexBuilder {
+    definitions: Vec<Definition>,
+    // name of each definition
+    names: Vec<String>,
+    // references not bound yet, with the scopes around them
+    unresolved: Vec<(Reference, Vec<usize>)>,
+    references: Vec<Reference>,
+    scopes: Vec<usize>,
+    next_scope: usize,
+}
+
+impl IndexBuilder {
+    fn define(&mut self, name: &str, span: Span, detail: String) {
+        let scope = *self
+            .scopes
+            .last()
+            .expect("the program scope is never popped");
+        self.references.push(Reference {
+            name: name.to_string(),
+            span,
+            definition: Some(self.definitions.len()),
+        });
+        self.definitions.push(Definition {
+            span,
+            detail,
+            scope,
+        });
+        self.names.push(name.to_string());
+    }
+
+    fn reference(&mut self, name: &str, span: Span) {
+        let reference = Reference {
+            name: name.to_string(),
+            span,
+            definition: None,
+        };
+        // the latest binding made so far in the innermost scope that has one
+        let definition = self.scopes.iter().rev().find_map(|scope| {
+            (0..self.definitions.len())
+                .rev()
+                .find(|idx| self.definitions[*idx].scope == *scope && self.names[*idx] == name)
+        });
+        match definition {
+            Some(definition) => self.references.push(Reference {
+                definition: Some(definition),
+                ..reference
+            }),
+            None => self.unresolved.push((reference, self.scopes.clone())),
+        }
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::Let(statement) => {
+                    self.expression(&statement.value);
+                    let detail = match &statement.value {
+                        Expression::Callable(CallableExpression::FunctionLiteral(literal)) => {
+                            format!("let {} = {}", statement.pattern, signature(literal))
+                        }
+                        value => format!("let {} = {}", statement.pattern, value),
+                    };
+                    self.pattern(&statement.pattern, &detail);
+                }
+                Statement::Return(statement) => self.expression(&statement.value),
+                Statement::Throw(statement) => self.expression(&statement.value),
+                Statement::Expression(statement) => self.expression(&statement.expression),
+                Statement::Block(block) => self.statements(&block.statements),
+            }
+        }
+    }
+
+    // Defines the names `pattern` binds, after the defaults it evaluates.
+    fn pattern(&mut self, pattern: &Pattern, detail: &str) {
+        for default in pattern.defaults() {
+            self.expression(default);
+        }
+        for name in pattern.bindings() {
+            self.define(&name.value, name.span, detail.to_string());
+        }
+    }
+
+    fn function(&mut self, literal: &FunctionLiteral) {
+        self.scopes.push(self.next_scope);
+        self.next_scope += 1;
+        for parameter in &literal.parameters {
+            let detail = format!("parameter {} of {}", parameter, signature(literal));
+            self.define(&parameter.value, parameter.span, detail);
+        }
+        self.statements(&literal.body.statements);
+        self.scopes.pop();
+    }
+
+    fn expression(&mut self, exp: &Expression) {
+        match exp {
+            Expression::Callable(CallableExpression::Identifier(ident)) => {
+                self.reference(&ident.value, ident.span)
+            }
+            Expression::Callable(CallableExpression::FunctionLiteral(literal)) => {
+                self.function(literal)
+            }
+            Expression::IntegerLiteral(_)
+            | Expression::StringLiteral(_)
+            | Expression::BooleanLiteral(_) => {}
+            Expression::ArrayLiteral(literal) => {
+                for element in &literal.elements {
+                    self.expression(element);
+                }
+            }
+            Expression::HashLiteral(literal) => {
+                for (key, value) in &literal.pairs {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            Expression::Prefix(exp) => self.expression(&exp.right),
+            Expression::Propagate(exp) => self.expression(&exp.value),
+            Expression::Infix(exp) => {
+                self.expression(&exp.left);
+                self.expression(&exp.right);
+            }
+            Expression::If(exp) => {
+                self.expression(&exp.condition);
+                self.statements(&exp.consequence.statements);
+                if let Some(alternative) = &exp.alternative {
+                    self.statements(&alternative.statements);
+                }
+            }
+            Expression::Try(exp) => {
+                self.statements(&exp.body.statements);
+                if let Some(catch) = &exp.catch {
+                    let detail = format!("catch ({})", catch.name);
+                    self.define(&catch.name.value, catch.name.span, detail);
+                    self.statements(&catch.body.statements);
+                }
+                if let Some(finally) = &exp.finally {
+                    self.statements(&finally.statements);
+                }
+            }
+            Expression::Match(exp) => {
+                self.expression(&exp.value);
+                for arm in &exp.arms {
+                    self.pattern(&arm.pattern, &format!("match arm {}", arm.pattern));
+                    if let Some(guard) = &arm.guard {
+                        self.expression(guard);
+                    }
+                    match &arm.body {
+                        ArmBody::Expression(exp) => self.expression(exp),
+                        ArmBody::Block(block) => self.statements(&block.statements),
+                    }
+                }
+            }
+            Expression::Call(call) => {
+                match &call.func {
+                    CallableExpression::Identifier(ident) => {
+                        self.reference(&ident.value, ident.span)
+                    }
+                    CallableExpression::FunctionLiteral(literal) => self.function(literal),
+                }
+                for argument in &call.arguments {
+                    self.expression(argument);
+                }
+            }
+        }
+    }
+}
+
+// `fn(a, b)`, without the body
+fn signature(literal: &FunctionLiteral) -> String {
+    let parameters: Vec<_> = literal.parameters.iter().map(|p| p.to_string()).collect();
+    format!("fn({})", parameters.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::{offset, position, read_message, run, write_message};
+
+    // Runs the client messages through the server, returning what it wrote back.
+    fn transcript(messages: &[Value]) -> Vec<Value> {
+        let mut input = Vec::new();
+        for message in messages {
+            write_message(&mut input, message).unwrap();
+        }
+        let mut output = Vec::new();
+        run(input.as_slice(), &mut output).unwrap();
+
+        let mut reader = output.as_slice();
+        let mut responses = Vec::new();
+        while let Some(body) = read_message(&mut reader).unwrap() {
+            responses.push(serde_json::from_str(&body).unwrap());
+        }
+        responses
+    }
+
+    fn request(id: i64, method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    fn notification(method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "method": method, "params": params })
+    }
+
+    fn at(line: u64, character: u64) -> Value {
+        json!({ "textDocument": { "uri": "file:///main.mk" }, "position": { "line": line, "character": character } })
+    }
+
+    fn range(start: (u64, u64), end: (u64, u64)) -> Value {
+        json!({
+            "start": { "line": start.0, "character": start.1 },
+            "end": { "line": end.0, "character": end.1 },
+        })
+    }
+
+    const SOURCE: &str = "let add = fn(a, b) {\n  let sum = a + b;\n  sum\n};\nputs(add(1, 2));\n";
+
+    #[test]
+    fn test_session() {
+        let responses = transcript(&[
+            request(1, "initialize", json!({ "capabilities": {} })),
+            notification("initialized", json!({})),
+            notification(
+                "textDocument/didOpen",
+                json!({ "textDocument": { "uri": "file:///main.mk", "languageId": "monkey", "version": 1, "text": SOURCE } }),
+            ),
+            request(2, "textDocument/hover", at(4, 6)),
+            request(3, "textDocument/definition", at(2, 3)),
+            request(
+                4,
+                "textDocument/documentSymbol",
+                json!({ "textDocument": { "uri": "file:///main.mk" } }),
+            ),
+            request(5, "textDocument/hover", at(4, 1)),
+            request(6, "textDocument/hover", at(0, 14)),
+            request(7, "textDocument/formatting", json!({})),
+            request(8, "shutdown", Value::Null),
+            notification("exit", Value::Null),
+            request(9, "shutdown", Value::Null),
+        ]);
+
+        assert_eq!(responses.len(), 9, "{:#?}", responses);
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(
+            responses[0]["result"]["capabilities"]["hoverProvider"],
+            true
+        );
+        assert_eq!(
+            responses[1],
+            notification(
+                "textDocument/publishDiagnostics",
+                json!({ "uri": "file:///main.mk", "diagnostics": [] })
+            )
+        );
+        assert_eq!(
+            responses[2],
+            json!({ "jsonrpc": "2.0", "id": 2, "result": {
+                "contents": { "kind": "markdown", "value": "```monkey\nlet add = fn(a, b)\n```" },
+                "range": range((4, 5), (4, 8)),
+            } })
+        );
+        assert_eq!(
+            responses[3]["result"],
+            json!({ "uri": "file:///main.mk", "range": range((1, 6), (1, 9)) })
+        );
+        assert_eq!(
+            responses[4]["result"],
+            json!([{
+                "name": "add",
+                "kind": 12,
+                "range": range((0, 0), (3, 2)),
+                "selectionRange": range((0, 4), (0, 7)),
+                "children": [{
+                    "name": "sum",
+                    "kind": 13,
+                    "range": range((1, 2), (1, 18)),
+                    "selectionRange": range((1, 6), (1, 9)),
+                    "children": [],
+                }],
+            }])
+        );
+        assert_eq!(
+            responses[5]["result"]["contents"]["value"],
+            "```monkey\nbuiltin puts\n```"
+        );
+        assert_eq!(
+            responses[6]["result"]["contents"]["value"],
+            "```monkey\nparameter a of fn(a, b)\n```"
+        );
+        assert_eq!(responses[7]["error"]["code"], -32601);
+        assert_eq!(
+            responses[8],
+            json!({ "jsonrpc": "2.0", "id": 8, "result": null })
+        );
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let open = |text: &str| {
+            notification(
+                "textDocument/didOpen",
+                json!({ "textDocument": { "uri": "file:///main.mk", "languageId": "monkey", "version": 1, "text": text } }),
+            )
+        };
+        let responses = transcript(&[
+            open("let x = 1;\nlet = 2;"),
+            notification(
+                "textDocument/didChange",
+                json!({ "textDocument": { "uri": "file:///main.mk", "version": 2 }, "contentChanges": [{ "text": "let x = 1;" }] }),
+            ),
+            open("let x = 99999999999999999999;\nx"),
+            notification(
+                "textDocument/didClose",
+                json!({ "textDocument": { "uri": "file:///main.mk" } }),
+            ),
+        ]);
+
+        let diagnostics: Vec<_> = responses
+            .iter()
+            .map(|response| response["params"]["diagnostics"].clone())
+            .collect();
+        assert_eq!(
+            diagnostics,
+            [
+                json!([
+                    {
+                        "range": range((1, 0), (1, 3)),
+                        "severity": 1,
+                        "source": "monkey",
+                        "message": "expected `let <pattern> =`, got `=`",
+                    },
+                    {
+                        "range": range((1, 4), (1, 5)),
+                        "severity": 1,
+                        "source": "monkey",
+                        "message": "unexpected `=` at the start of an expression",
+                    },
+                ]),
+                json!([{
+                    "range": range((0, 4), (0, 5)),
+                    "severity": 2,
+                    "source": "monkey",
+                    "message": "unused binding `x`",
+                    "code": "unused-let",
+                }]),
+                json!([{
+                    "range": range((0, 8), (0, 28)),
+                    "severity": 1,
+                    "source": "monkey",
+                    "message": "integer literal `99999999999999999999` does not fit in 64 bits",
+                }, {
+                    "range": range((0, 28), (0, 29)),
+                    "severity": 1,
+                    "source": "monkey",
+                    "message": "unexpected `;` at the start of an expression",
+                }]),
+                json!([]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_completion_and_mutual_recursion() {
+        let source = "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } };\nlet odd = fn(n) { even(n) };";
+        let responses = transcript(&[
+            notification(
+                "textDocument/didOpen",
+                json!({ "textDocument": { "uri": "file:///main.mk", "languageId": "monkey", "version": 1, "text": source } }),
+            ),
+            request(1, "textDocument/definition", at(0, 49)),
+            request(2, "textDocument/completion", at(1, 0)),
+            request(3, "textDocument/hover", json!({})),
+        ]);
+
+        assert_eq!(responses[1]["result"]["range"], range((1, 4), (1, 7)));
+        let labels: Vec<_> = responses[2]["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                "let",
+                "fn",
+                "if",
+                "else",
+                "true",
+                "false",
+                "return",
+                "throw",
+                "try",
+                "catch",
+                "finally",
+                "match",
+                "puts",
+                "gets",
+                "error_message",
+                "error_kind",
+                "error_trace",
+                "ok",
+                "err",
+                "is_ok",
+                "unwrap_or"
+            ]
+        );
+        assert_eq!(responses[3]["error"]["code"], -32602);
+    }
+
+    #[test]
+    fn test_positions() {
+        let source = "let é = \"😀\";\nx";
+        let tests = vec![
+            (0, (0, 0)),
+            (6, (0, 5)),
+            (9, (0, 8)),
+            (14, (0, 11)),
+            (17, (1, 0)),
+        ];
+
+        for (byte, (line, character)) in tests {
+            let pos = json!({ "line": line, "character": character });
+            assert_eq!(position(source, byte), pos, "{}", byte);
+            assert_eq!(offset(source, &pos), Some(byte), "{}", byte);
+        }
+        assert_eq!(
+            offset(source, &json!({ "line": 0, "character": 99 })),
+            Some(16)
+        );
+        assert_eq!(offset(source, &json!({ "line": 5, "character": 0 })), None);
+    }
+}
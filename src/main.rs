@@ -1,16 +1,253 @@
-use anyhow::Result;
-use std::io::{self};
-use thorsten_interpreter::repl::Repl;
+use anyhow::{anyhow, bail, Context, Result};
+use std::{
+    fs,
+    io::{self, BufReader, IsTerminal, Read},
+    path::PathBuf,
+    process,
+};
+use thorsten_interpreter::{
+    diagnostic::Severity,
+    formatter::format_source,
+    interpreter::{Interpreter, ParseError},
+    line_editor::{default_history_path, LineEditor},
+    lint::{lint_source, LintConfig, Rule},
+    repl::Repl,
+    CancellationToken, DEFAULT_MAX_CALL_DEPTH,
+};
 
-fn main() -> Result<()> {
-    println!(
-        "Hello {}! This is the Monkey programming language!",
-        whoami::username()
-    );
-    println!("Feel free to type in commands");
+struct Options {
+    max_call_depth: usize,
+    // evaluated before the session or piped program
+    prelude: Option<PathBuf>,
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options> {
+    let mut max_call_depth = DEFAULT_MAX_CALL_DEPTH;
+    let mut prelude = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--max-call-depth" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--max-call-depth expects a value"))?;
+                max_call_depth = value
+                    .parse()
+                    .with_context(|| format!("invalid --max-call-depth: {}", value))?;
+            }
+            "--prelude" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--prelude expects a file"))?;
+                prelude = Some(PathBuf::from(value));
+            }
+            _ => bail!("unknown argument: {}", arg),
+        }
+    }
+    Ok(Options {
+        max_call_depth,
+        prelude,
+    })
+}
+
+fn print_error(err: &anyhow::Error) {
+    match err.downcast_ref::<ParseError>() {
+        Some(parse_error) => {
+            for err in &parse_error.errors {
+                eprintln!("Error {}", err);
+            }
+        }
+        None => eprintln!("Error {}", err),
+    }
+}
+
+// `fmt [--check] [files]` rewrites files in place, or stdin to stdout. With `--check` it
+// only lists the files that are not formatted, failing if there are any.
+fn run_fmt(args: impl Iterator<Item = String>) -> Result<()> {
+    let mut check = false;
+    let mut files = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            _ if arg.starts_with("--") => bail!("unknown argument: {}", arg),
+            _ => files.push(PathBuf::from(arg)),
+        }
+    }
+
+    if files.is_empty() {
+        let mut source = String::new();
+        io::stdin()
+            .read_to_string(&mut source)
+            .context("failed to read program from stdin")?;
+        let formatted = format_source(&source).unwrap_or_else(|err| {
+            print_error(&err);
+            process::exit(1);
+        });
+        if check {
+            if formatted != source {
+                eprintln!("stdin is not formatted");
+                process::exit(1);
+            }
+        } else {
+            print!("{}", formatted);
+        }
+        return Ok(());
+    }
+
+    let mut failed = false;
+    for file in files {
+        let source = fs::read_to_string(&file)
+            .with_context(|| format!("unable to read {}", file.display()))?;
+        let formatted = match format_source(&source) {
+            Ok(formatted) => formatted,
+            Err(err) => {
+                eprintln!("{}:", file.display());
+                print_error(&err);
+                failed = true;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        if check {
+            eprintln!("{} is not formatted", file.display());
+            failed = true;
+        } else {
+            fs::write(&file, formatted)
+                .with_context(|| format!("unable to write {}", file.display()))?;
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+    Ok(())
+}
 
-    let repl = Repl::new(io::stdin().lock(), io::stdout().lock());
-    repl.start()?;
+// `lint [--rule <rule>=<off|info|warning|error>] [files]` prints the diagnostics of the
+// files, or stdin, failing if any is an error.
+fn run_lint(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let mut config = LintConfig::default();
+    let mut files = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rule" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--rule expects <rule>=<severity>"))?;
+                let (name, severity) = value
+                    .split_once('=')
+                    .ok_or_else(|| anyhow!("--rule expects <rule>=<severity>"))?;
+                let rule = Rule::lookup(name).ok_or_else(|| anyhow!("unknown rule: {}", name))?;
+                let severity = match severity {
+                    "off" => None,
+                    "info" => Some(Severity::Info),
+                    "warning" => Some(Severity::Warning),
+                    "error" => Some(Severity::Error),
+                    _ => bail!("unknown severity: {}", severity),
+                };
+                config = config.with_rule(rule, severity);
+            }
+            _ if arg.starts_with("--") => bail!("unknown argument: {}", arg),
+            _ => files.push(PathBuf::from(arg)),
+        }
+    }
 
+    let mut sources = Vec::new();
+    if files.is_empty() {
+        let mut source = String::new();
+        io::stdin()
+            .read_to_string(&mut source)
+            .context("failed to read program from stdin")?;
+        sources.push(("<stdin>".to_string(), source));
+    }
+    for file in files {
+        let source = fs::read_to_string(&file)
+            .with_context(|| format!("unable to read {}", file.display()))?;
+        sources.push((file.display().to_string(), source));
+    }
+
+    let mut failed = false;
+    for (name, source) in sources {
+        for diagnostic in lint_source(&source, &config) {
+            failed |= diagnostic.severity == Severity::Error;
+            print!("{}", diagnostic.render(&source, &name));
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+// Piped input is a program rather than a session: it is evaluated as a whole, only
+// `puts` prints, and errors go to stderr with a failing exit status. The prelude is
+// evaluated on its own first, so the lines of the program keep their numbers.
+fn run_script(options: &Options, cancellation: CancellationToken) -> Result<()> {
+    let mut source = String::new();
+    io::stdin()
+        .read_to_string(&mut source)
+        .context("failed to read program from stdin")?;
+
+    let mut interpreter = Interpreter::new()
+        .with_max_call_depth(options.max_call_depth)
+        .with_cancellation_token(cancellation);
+    if let Some(prelude) = &options.prelude {
+        let prelude_source = fs::read_to_string(prelude)
+            .with_context(|| format!("unable to read prelude {}", prelude.display()))?;
+        if let Err(err) = interpreter.eval_str(&prelude_source) {
+            eprintln!("Error failed to evaluate prelude {}", prelude.display());
+            print_error(&err);
+            process::exit(1);
+        }
+    }
+    if let Err(err) = interpreter.eval_str(&source) {
+        print_error(&err);
+        process::exit(1);
+    }
     Ok(())
 }
+
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("fmt") {
+        return run_fmt(args.skip(1));
+    }
+    if args.peek().map(String::as_str) == Some("lint") {
+        return run_lint(args.skip(1));
+    }
+    let options = parse_options(args)?;
+    let interactive = io::stdin().is_terminal();
+
+    if interactive {
+        println!(
+            "Hello {}! This is the Monkey programming language!",
+            whoami::username()
+        );
+        println!("Feel free to type in commands, :help lists the REPL commands");
+        println!("Press Ctrl-C to interrupt an evaluation, Ctrl-D to quit");
+    }
+
+    // The REPL clears the token after every evaluation, so a second Ctrl-C before it does
+    // means nothing was listening for the first one.
+    let cancellation = CancellationToken::new();
+    let handler_cancellation = cancellation.clone();
+    ctrlc::set_handler(move || {
+        if handler_cancellation.is_cancelled() {
+            process::exit(130);
+        }
+        handler_cancellation.cancel();
+    })
+    .context("unable to install Ctrl-C handler")?;
+
+    if !interactive {
+        return run_script(&options, cancellation);
+    }
+    let mut repl = Repl::new(BufReader::new(io::stdin()), io::stdout())
+        .with_line_editor(LineEditor::new(default_history_path())?)
+        .with_max_call_depth(options.max_call_depth)
+        .with_cancellation_token(cancellation);
+    if let Some(prelude) = &options.prelude {
+        repl = repl.with_prelude(prelude)?;
+    }
+    repl.start()
+}
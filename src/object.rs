@@ -1,50 +1,249 @@
-use std::fmt::{Debug, Display};
+use std::{
+    collections::BTreeMap,
+    fmt::{Debug, Display},
+    sync::Arc,
+};
+
+use anyhow::Result;
 
 use crate::{
     ast::{BlockStatement, Identifier},
+    builtins::Builtin,
     environment::Environment,
+    evaluator::Frame,
 };
 
+/// A Monkey value.
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[non_exhaustive]
 pub enum Object {
     Integer(i64),
     Boolean(bool),
     String(String),
-    ReturnValue(Box<Object>),
+    Array(Vec<Object>),
+    Hash(BTreeMap<HashKey, Object>),
+    /// Only carries control flow inside the evaluator, it is never the result of an
+    /// evaluation and cannot be created outside of it.
+    ControlFlow(ControlFlow),
     Function(FunctionObj),
+    HostFunction(HostFunction),
+    Builtin(Builtin),
+    Error(ErrorObj),
+    /// Results of `ok(v)` and `err(e)`, unwrapped by `?`.
+    Ok(Box<Object>),
+    Err(Box<Object>),
     Null,
 }
 
+/// The values that can be used as keys of a hash.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub enum HashKey {
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+}
+
+impl HashKey {
+    pub fn type_val(&self) -> &'static str {
+        match self {
+            HashKey::Integer(_) => "INTEGER",
+            HashKey::Boolean(_) => "BOOLEAN",
+            HashKey::String(_) => "STRING",
+        }
+    }
+}
+
+impl From<HashKey> for Object {
+    fn from(value: HashKey) -> Self {
+        match value {
+            HashKey::Integer(n) => Object::Integer(n),
+            HashKey::Boolean(b) => Object::Boolean(b),
+            HashKey::String(s) => Object::String(s),
+        }
+    }
+}
+
+impl Display for HashKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HashKey::Integer(n) => write!(f, "{}", n),
+            HashKey::Boolean(b) => write!(f, "{}", b),
+            HashKey::String(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+/// A `return` or a tail call on its way out of the function making it.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ControlFlow(pub(crate) Flow);
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub(crate) enum Flow {
+    Return(Box<Object>),
+    // A call in tail position, handed back to the caller's trampoline instead of
+    // being evaluated on a new Rust frame.
+    TailCall(Box<TailCallObj>),
+}
+
+/// A function written in Monkey, only created by evaluating a function literal.
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct FunctionObj {
-    pub arguments: Vec<Identifier>,
-    pub body: BlockStatement,
-    pub env: Environment,
+    /// Name of the `let` that first bound it.
+    pub name: Option<String>,
+    pub(crate) arguments: Vec<Identifier>,
+    pub(crate) body: BlockStatement,
+    pub(crate) env: Environment,
+}
+
+type HostFn = dyn Fn(&[Object]) -> Result<Object> + Send + Sync;
+
+/// A function implemented by the host application, called by scripts like any other
+/// function. Errors it returns become runtime errors of the calling script. Like every
+/// other value it can be shared between threads, so the closure must be `Send + Sync`.
+#[derive(Clone)]
+pub struct HostFunction {
+    pub name: String,
+    func: Arc<HostFn>,
+}
+
+impl HostFunction {
+    pub fn new<F>(name: impl Into<String>, func: F) -> Self
+    where
+        F: Fn(&[Object]) -> Result<Object> + Send + Sync + 'static,
+    {
+        Self {
+            name: name.into(),
+            func: Arc::new(func),
+        }
+    }
+
+    pub fn call(&self, args: &[Object]) -> Result<Object> {
+        (self.func)(args)
+    }
+}
+
+impl PartialEq for HostFunction {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.func, &other.func)
+    }
+}
+
+impl Eq for HostFunction {}
+
+impl Debug for HostFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HostFunction")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+/// A runtime error or a thrown value, as `catch` binds it. Raised as an `anyhow` error
+/// by `throw`, which displays as its message.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ErrorObj {
+    /// `TypeError`, `NameError`, `RecursionError` or `RuntimeError` for errors of the
+    /// interpreter, `Error` for other thrown values.
+    pub kind: String,
+    pub message: String,
+    /// Calls in progress where it was first raised, innermost last.
+    pub trace: Vec<Frame>,
+}
+
+impl Display for ErrorObj {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ErrorObj {}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub(crate) struct TailCallObj {
+    pub func: FunctionObj,
+    pub arguments: Vec<Object>,
+    pub frame: Frame,
 }
 
 impl Object {
+    pub(crate) fn return_value(obj: Object) -> Self {
+        Object::ControlFlow(ControlFlow(Flow::Return(Box::new(obj))))
+    }
+
+    pub(crate) fn tail_call(call: TailCallObj) -> Self {
+        Object::ControlFlow(ControlFlow(Flow::TailCall(Box::new(call))))
+    }
+
     pub fn is_thruthy(&self) -> bool {
         match self {
             Object::Integer(_) => true,
             Object::Boolean(b) => *b,
             Object::Null => false,
-            Object::ReturnValue(obj) => obj.is_thruthy(),
+            Object::ControlFlow(ControlFlow(Flow::Return(obj))) => obj.is_thruthy(),
+            Object::ControlFlow(ControlFlow(Flow::TailCall(_))) => true,
             Object::Function(_) => true,
+            Object::HostFunction(_) => true,
+            Object::Builtin(_) => true,
             Object::String(_) => true,
+            Object::Array(_) => true,
+            Object::Hash(_) => true,
+            Object::Error(_) => true,
+            Object::Ok(_) => true,
+            Object::Err(_) => true,
         }
     }
+    /// The value as Monkey source would write it, so strings are quoted.
+    ///
+    /// ```
+    /// use thorsten_interpreter::object::Object;
+    ///
+    /// let greeting = Object::String("hi".to_string());
+    /// assert_eq!(greeting.to_string(), "hi");
+    /// assert_eq!(greeting.inspect(), "\"hi\"");
+    /// assert_eq!(Object::Array(vec![greeting]).to_string(), "[\"hi\"]");
+    /// ```
+    pub fn inspect(&self) -> String {
+        match self {
+            Object::String(s) => format!("\"{}\"", s),
+            obj => obj.to_string(),
+        }
+    }
+
     pub fn type_val(&self) -> &'static str {
         match self {
             Object::Integer(_) => "INTEGER",
             Object::Boolean(_) => "BOOLEAN",
-            Object::ReturnValue(_) => "RETURN",
+            Object::ControlFlow(ControlFlow(Flow::Return(_))) => "RETURN",
+            Object::ControlFlow(ControlFlow(Flow::TailCall(_))) => "TAIL_CALL",
             Object::Null => "NULL",
             Object::Function(_) => "FUNCTION",
+            Object::HostFunction(_) => "HOST_FUNCTION",
+            Object::Builtin(_) => "BUILTIN",
             Object::String(_) => "STRING",
+            Object::Array(_) => "ARRAY",
+            Object::Hash(_) => "HASH",
+            Object::Error(_) => "ERROR",
+            Object::Ok(_) => "OK",
+            Object::Err(_) => "ERR",
         }
     }
 }
 
+impl Display for FunctionObj {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "fn(")?;
+        for (idx, argument) in self.arguments.iter().enumerate() {
+            if idx > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", argument)?;
+        }
+        write!(f, ") {}", self.body)
+    }
+}
+
+/// Strings print as their contents, for `puts`. Inside arrays and hashes, and as REPL
+/// results, they are quoted instead, see [`Object::inspect`].
 impl Display for Object {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -58,9 +257,34 @@ impl Display for Object {
                 }
             }
             Object::Null => write!(f, "null"),
-            Object::ReturnValue(obj) => std::fmt::Display::fmt(&obj, f),
-            Object::Function(func) => {
-                write!(f, "fn({:?})\n{{\n}}", func.arguments)
+            Object::ControlFlow(ControlFlow(Flow::Return(obj))) => std::fmt::Display::fmt(&obj, f),
+            Object::ControlFlow(ControlFlow(Flow::TailCall(_))) => write!(f, "tail call"),
+            Object::Function(func) => write!(f, "{}", func),
+            Object::HostFunction(func) => write!(f, "host function {}", func.name),
+            Object::Builtin(builtin) => write!(f, "builtin function {}", builtin.name()),
+            Object::Error(error) => write!(f, "{}: {}", error.kind, error.message),
+            Object::Ok(value) => write!(f, "ok({})", value.inspect()),
+            Object::Err(error) => write!(f, "err({})", error.inspect()),
+            Object::Array(elements) => {
+                write!(f, "[")?;
+                for (idx, element) in elements.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element.inspect())?;
+                }
+                write!(f, "]")
+            }
+            Object::Hash(pairs) => {
+                write!(f, "{{")?;
+                for (idx, (key, value)) in pairs.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    let key = Object::from(key.clone());
+                    write!(f, "{}: {}", key.inspect(), value.inspect())?;
+                }
+                write!(f, "}}")
             }
         }
     }
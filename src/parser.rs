@@ -1,11 +1,15 @@
 use crate::{
     ast::{
-        BlockStatement, CallExpression, CallableExpression, Expression, ExpressionStatement,
-        FunctionLiteral, Identifier, IfExpression, InfixExpression, IntegerLiteral, LetStatement,
-        Operator, PrefixExpression, Program, ReturnStatement, Statement, StringLiteral,
+        ArmBody, ArrayLiteral, ArrayPattern, BlockStatement, CallExpression, CallableExpression,
+        CatchClause, DefaultPattern, Expression, ExpressionStatement, FieldPattern,
+        FunctionLiteral, HashLiteral, HashPattern, Identifier, IfExpression, InfixExpression,
+        IntegerLiteral, LetStatement, MatchArm, MatchExpression, Operator, Pattern,
+        PrefixExpression, Program, PropagateExpression, ReturnStatement, Statement, StringLiteral,
+        ThrowStatement, TryExpression,
     },
+    diagnostic::Diagnostic,
     lexer::Lexer,
-    token::{Token, TokenKind},
+    token::{Span, Token, TokenKind},
 };
 use anyhow::{bail, Result};
 
@@ -13,6 +17,10 @@ pub struct Parser<'a> {
     lexer: Lexer<'a>,
     current_token: Option<Token<'a>>,
     peek_token: Option<Token<'a>>,
+    // span of the last token once the input runs out
+    last_span: Span,
+    // byte offset of the start of every line
+    line_starts: Vec<usize>,
 }
 
 impl<'a> Token<'a> {
@@ -21,6 +29,7 @@ impl<'a> Token<'a> {
             TokenKind::Ident => Ok(Expression::Callable(CallableExpression::Identifier(
                 Identifier {
                     value: self.val.to_string(),
+                    span: self.span,
                 },
             ))),
             TokenKind::Bang | TokenKind::Minus => {
@@ -28,24 +37,29 @@ impl<'a> Token<'a> {
 
                 let right = parser.parse_expression(OperatorPrecedence::Prefix)?;
                 Ok(Expression::Prefix(PrefixExpression {
+                    span: self.span.to(right.span()),
                     right: Box::new(right),
                     operator: self.try_into()?,
                 }))
             }
-            TokenKind::Int => Ok(Expression::IntegerLiteral(IntegerLiteral {
-                value: self
-                    .val
-                    .parse()
-                    .expect("was str => int, already validated by token"),
-            })),
+            TokenKind::Int => match self.val.parse() {
+                Ok(value) => Ok(Expression::IntegerLiteral(IntegerLiteral {
+                    value,
+                    span: self.span,
+                })),
+                Err(_) => bail!("integer literal `{}` does not fit in 64 bits", self.val),
+            },
             TokenKind::String => Ok(Expression::StringLiteral(StringLiteral {
                 value: self.val.to_string(),
+                span: self.span,
             })),
             TokenKind::True => Ok(Expression::BooleanLiteral(crate::ast::BooleanLiteral {
                 value: true,
+                span: self.span,
             })),
             TokenKind::False => Ok(Expression::BooleanLiteral(crate::ast::BooleanLiteral {
                 value: false,
+                span: self.span,
             })),
             TokenKind::Lparen => {
                 parser.next_token();
@@ -58,6 +72,42 @@ impl<'a> Token<'a> {
                     bail!("right parentesis not found after left")
                 }
             }
+            TokenKind::Lbracket => {
+                let mut elements = Vec::new();
+                while parser.peek_token_kind() != Some(TokenKind::Rbracket) {
+                    parser.next_token();
+                    elements.push(parser.parse_expression(OperatorPrecedence::Lowest)?);
+                    if parser.peek_token_kind() != Some(TokenKind::Comma) {
+                        break;
+                    }
+                    parser.next_token();
+                }
+                parser.expect_peek(TokenKind::Rbracket, "`,` or `]` in an array")?;
+                Ok(Expression::ArrayLiteral(ArrayLiteral {
+                    elements,
+                    span: self.span.to(parser.current_span()),
+                }))
+            }
+            TokenKind::Lbrace => {
+                let mut pairs = Vec::new();
+                while parser.peek_token_kind() != Some(TokenKind::Rbrace) {
+                    parser.next_token();
+                    let key = parser.parse_expression(OperatorPrecedence::Lowest)?;
+                    parser.expect_peek(TokenKind::Colon, "`:` after a hash key")?;
+                    parser.next_token();
+                    let value = parser.parse_expression(OperatorPrecedence::Lowest)?;
+                    pairs.push((key, value));
+                    if parser.peek_token_kind() != Some(TokenKind::Comma) {
+                        break;
+                    }
+                    parser.next_token();
+                }
+                parser.expect_peek(TokenKind::Rbrace, "`,` or `}` in a hash")?;
+                Ok(Expression::HashLiteral(HashLiteral {
+                    pairs,
+                    span: self.span.to(parser.current_span()),
+                }))
+            }
             TokenKind::Function => {
                 if let Some(TokenKind::Lparen) = parser.peek_token_kind() {
                     parser.next_token();
@@ -75,7 +125,11 @@ impl<'a> Token<'a> {
                 let body = parser.parse_block_statement()?;
 
                 Ok(Expression::Callable(CallableExpression::FunctionLiteral(
-                    FunctionLiteral { body, parameters },
+                    FunctionLiteral {
+                        span: self.span.to(body.span),
+                        body,
+                        parameters,
+                    },
                 )))
             }
             TokenKind::If => {
@@ -113,19 +167,87 @@ impl<'a> Token<'a> {
                     let alternative = parser.parse_block_statement()?;
 
                     Ok(Expression::If(IfExpression {
+                        span: self.span.to(alternative.span),
                         condition: Box::new(condition),
                         consequence,
                         alternative: Some(alternative),
                     }))
                 } else {
                     Ok(Expression::If(IfExpression {
+                        span: self.span.to(consequence.span),
                         condition: Box::new(condition),
                         consequence,
                         alternative: None,
                     }))
                 }
             }
-            _ => bail!("test broken exp {:?}", &self),
+            TokenKind::Try => {
+                parser.expect_peek(TokenKind::Lbrace, "`{` after `try`")?;
+                let body = parser.parse_block_statement()?;
+                let mut end = body.span;
+
+                let mut catch = None;
+                if let Some(TokenKind::Catch) = parser.peek_token_kind() {
+                    parser.next_token();
+                    parser.expect_peek(TokenKind::Lparen, "`(` after `catch`")?;
+                    parser.expect_peek(TokenKind::Ident, "the name of the caught error")?;
+                    let name = Identifier {
+                        value: parser.current_token.clone().unwrap().val.to_string(),
+                        span: parser.current_span(),
+                    };
+                    parser.expect_peek(TokenKind::Rparen, "`)` after the caught error")?;
+                    parser.expect_peek(TokenKind::Lbrace, "`{` after `catch (...)`")?;
+                    let body = parser.parse_block_statement()?;
+                    end = body.span;
+                    catch = Some(CatchClause { name, body });
+                }
+
+                let mut finally = None;
+                if let Some(TokenKind::Finally) = parser.peek_token_kind() {
+                    parser.next_token();
+                    parser.expect_peek(TokenKind::Lbrace, "`{` after `finally`")?;
+                    let body = parser.parse_block_statement()?;
+                    end = body.span;
+                    finally = Some(body);
+                }
+
+                if catch.is_none() && finally.is_none() {
+                    bail!(
+                        "expected `catch` or `finally` after the `try` block, got {}",
+                        parser.peek_description()
+                    );
+                }
+                Ok(Expression::Try(TryExpression {
+                    body,
+                    catch,
+                    finally,
+                    span: self.span.to(end),
+                }))
+            }
+            TokenKind::Match => {
+                parser.expect_peek(TokenKind::Lparen, "`(` after `match`")?;
+                parser.next_token();
+                let value = parser.parse_expression(OperatorPrecedence::Lowest)?;
+                parser.expect_peek(TokenKind::Rparen, "`)` after the matched value")?;
+                parser.expect_peek(TokenKind::Lbrace, "`{` after `match (...)`")?;
+
+                let mut arms = Vec::new();
+                while parser.peek_token_kind() != Some(TokenKind::Rbrace) {
+                    parser.next_token();
+                    arms.push(parser.parse_match_arm()?);
+                    if parser.peek_token_kind() != Some(TokenKind::Comma) {
+                        break;
+                    }
+                    parser.next_token();
+                }
+                parser.expect_peek(TokenKind::Rbrace, "`,` or `}` after a match arm")?;
+                Ok(Expression::Match(MatchExpression {
+                    value: Box::new(value),
+                    arms,
+                    span: self.span.to(parser.current_span()),
+                }))
+            }
+            _ => bail!("unexpected `{}` at the start of an expression", self.val),
         }
     }
 
@@ -146,6 +268,7 @@ impl<'a> Token<'a> {
                 parser.next_token();
                 let right = parser.parse_expression(precedence)?;
                 Ok(Expression::Infix(InfixExpression {
+                    span: left.span().to(right.span()),
                     right: Box::new(right),
                     left: Box::new(left),
                     operator: op,
@@ -154,20 +277,30 @@ impl<'a> Token<'a> {
             TokenKind::Lparen => {
                 if let Expression::Callable(callable) = left {
                     parser.next_token();
+                    let arguments = parser.parse_call_arguments()?;
                     return Ok(Expression::Call(CallExpression {
+                        span: callable.span().to(parser.current_span()),
+                        line: parser.line(callable.span().start),
                         func: callable,
-                        arguments: parser.parse_call_arguments()?,
+                        arguments,
                     }));
                 }
                 bail!("expression is not callable")
             }
+            TokenKind::Question => {
+                parser.next_token();
+                Ok(Expression::Propagate(PropagateExpression {
+                    span: left.span().to(parser.current_span()),
+                    value: Box::new(left),
+                }))
+            }
             _ => Ok(left),
         }
     }
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
-enum OperatorPrecedence {
+pub(crate) enum OperatorPrecedence {
     Lowest = 0,
     Equals = 1,
     LessGreater = 2,
@@ -188,7 +321,7 @@ impl From<&Operator> for OperatorPrecedence {
             Operator::NotEq => Self::Equals,
             Operator::Lt => Self::LessGreater,
             Operator::Gt => Self::LessGreater,
-            Operator::Lparen => Self::Call,
+            Operator::Lparen | Operator::Question => Self::Call,
             _ => Self::Lowest,
         }
     }
@@ -196,10 +329,15 @@ impl From<&Operator> for OperatorPrecedence {
 
 impl<'a> Parser<'a> {
     pub fn new(lexer: Lexer) -> Parser {
+        let line_starts = std::iter::once(0)
+            .chain(lexer.input().match_indices('\n').map(|(idx, _)| idx + 1))
+            .collect();
         let mut p = Parser {
             lexer,
             current_token: None,
             peek_token: None,
+            last_span: Span::default(),
+            line_starts,
         };
         p.next_token();
         p.next_token();
@@ -207,10 +345,41 @@ impl<'a> Parser<'a> {
     }
 
     fn next_token(&mut self) {
+        if let Some(token) = &self.current_token {
+            self.last_span = token.span;
+        }
         self.current_token = self.peek_token.take();
         self.peek_token = self.lexer.next_token();
     }
 
+    // the next token as error messages quote it
+    fn peek_description(&self) -> String {
+        self.peek_token
+            .as_ref()
+            .map_or("end of input".to_string(), |token| {
+                format!("`{}`", token.val)
+            })
+    }
+
+    /// Moves to the next token, which must be a `kind`.
+    fn expect_peek(&mut self, kind: TokenKind, expected: &str) -> Result<()> {
+        if self.peek_token_kind() != Some(kind) {
+            bail!("expected {}, got {}", expected, self.peek_description());
+        }
+        self.next_token();
+        Ok(())
+    }
+
+    fn line(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset)
+    }
+
+    fn current_span(&self) -> Span {
+        self.current_token
+            .as_ref()
+            .map_or(self.last_span, |token| token.span)
+    }
+
     pub fn peek_token_kind(&mut self) -> Option<TokenKind> {
         self.peek_token.as_ref().map(|token| token.kind.clone())
     }
@@ -220,12 +389,12 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_let_statement(&mut self) -> Result<Statement> {
-        if let Some(TokenKind::Ident) = self.peek_token_kind() {
+        let start = self.current_span();
+        if let Some(TokenKind::Ident | TokenKind::Lbracket | TokenKind::Lbrace) =
+            self.peek_token_kind()
+        {
             self.next_token();
-
-            let name = Identifier {
-                value: self.current_token.clone().unwrap().val.to_string(),
-            };
+            let pattern = self.parse_whole_pattern()?;
 
             if let Some(TokenKind::Assign) = self.peek_token_kind() {
                 self.next_token();
@@ -237,26 +406,177 @@ impl<'a> Parser<'a> {
                     self.next_token();
                 }
 
-                let statement = Ok(Statement::Let(LetStatement { name, value }));
+                let statement = Ok(Statement::Let(LetStatement {
+                    pattern,
+                    value,
+                    span: start.to(self.current_span()),
+                }));
                 return statement;
             }
         };
-        bail!("expected token to be ident got: {:?}", self.peek_token);
+        bail!(
+            "expected `let <pattern> =`, got {}",
+            self.peek_description()
+        );
     }
 
     fn parse_return_statement(&mut self) -> Result<Statement> {
+        let start = self.current_span();
         self.next_token();
         let value = self.parse_expression(OperatorPrecedence::Lowest)?;
 
         if let Some(TokenKind::Semicolon) = self.peek_token_kind() {
             self.next_token();
         }
-        Ok(Statement::Return(ReturnStatement { value }))
+        Ok(Statement::Return(ReturnStatement {
+            value,
+            span: start.to(self.current_span()),
+        }))
+    }
+
+    fn parse_throw_statement(&mut self) -> Result<Statement> {
+        let start = self.current_span();
+        self.next_token();
+        let value = self.parse_expression(OperatorPrecedence::Lowest)?;
+
+        if let Some(TokenKind::Semicolon) = self.peek_token_kind() {
+            self.next_token();
+        }
+        Ok(Statement::Throw(ThrowStatement {
+            value,
+            span: start.to(self.current_span()),
+        }))
+    }
+
+    fn parse_match_arm(&mut self) -> Result<MatchArm> {
+        let pattern = self.parse_whole_pattern()?;
+        let mut guard = None;
+        if let Some(TokenKind::If) = self.peek_token_kind() {
+            self.next_token();
+            self.next_token();
+            guard = Some(self.parse_expression(OperatorPrecedence::Lowest)?);
+        }
+        self.expect_peek(TokenKind::Arrow, "`=>` after the pattern")?;
+        self.next_token();
+        let body = if let Some(TokenKind::Lbrace) = self.current_token_kind() {
+            ArmBody::Block(self.parse_block_statement()?)
+        } else {
+            ArmBody::Expression(self.parse_expression(OperatorPrecedence::Lowest)?)
+        };
+        Ok(MatchArm {
+            pattern,
+            guard,
+            body,
+        })
+    }
+
+    // A pattern that is matched as a whole, so binds every name at most once.
+    fn parse_whole_pattern(&mut self) -> Result<Pattern> {
+        let pattern = self.parse_pattern()?;
+        let bindings = pattern.bindings();
+        for (i, ident) in bindings.iter().enumerate() {
+            if bindings[..i].iter().any(|other| other.value == ident.value) {
+                bail!("`{}` is bound more than once in the pattern", ident.value);
+            }
+        }
+        Ok(pattern)
+    }
+
+    // The pattern starting at the current token.
+    fn parse_pattern(&mut self) -> Result<Pattern> {
+        let Some(token) = self.current_token.clone() else {
+            bail!("expected a pattern, got end of input");
+        };
+        match token.kind {
+            TokenKind::Ident if token.val == "_" => Ok(Pattern::Wildcard(token.span)),
+            TokenKind::Ident => Ok(Pattern::Binding(Identifier {
+                value: token.val.to_string(),
+                span: token.span,
+            })),
+            TokenKind::Int | TokenKind::String | TokenKind::True | TokenKind::False => {
+                Ok(Pattern::Literal(token.prefix_parse(self)?))
+            }
+            TokenKind::Minus if self.peek_token_kind() == Some(TokenKind::Int) => {
+                Ok(Pattern::Literal(token.prefix_parse(self)?))
+            }
+            TokenKind::Lbracket => {
+                let mut elements = Vec::new();
+                let mut rest = None;
+                while self.peek_token_kind() != Some(TokenKind::Rbracket) {
+                    self.next_token();
+                    if let Some(TokenKind::Ellipsis) = self.current_token_kind() {
+                        self.expect_peek(TokenKind::Ident, "the name of the rest")?;
+                        rest = Some(Identifier {
+                            value: self.current_token.clone().unwrap().val.to_string(),
+                            span: self.current_span(),
+                        });
+                        break;
+                    }
+                    let element = self.parse_pattern()?;
+                    elements.push(self.parse_default(element)?);
+                    if self.peek_token_kind() != Some(TokenKind::Comma) {
+                        break;
+                    }
+                    self.next_token();
+                }
+                self.expect_peek(TokenKind::Rbracket, "`,` or `]` in an array pattern")?;
+                Ok(Pattern::Array(ArrayPattern {
+                    elements,
+                    rest,
+                    span: token.span.to(self.current_span()),
+                }))
+            }
+            TokenKind::Lbrace => {
+                let mut fields = Vec::new();
+                while self.peek_token_kind() != Some(TokenKind::Rbrace) {
+                    self.expect_peek(TokenKind::Ident, "a key in a hash pattern")?;
+                    let key = Identifier {
+                        value: self.current_token.clone().unwrap().val.to_string(),
+                        span: self.current_span(),
+                    };
+                    let pattern = if let Some(TokenKind::Colon) = self.peek_token_kind() {
+                        self.next_token();
+                        self.next_token();
+                        self.parse_pattern()?
+                    } else {
+                        Pattern::Binding(key.clone())
+                    };
+                    let pattern = self.parse_default(pattern)?;
+                    fields.push(FieldPattern { key, pattern });
+                    if self.peek_token_kind() != Some(TokenKind::Comma) {
+                        break;
+                    }
+                    self.next_token();
+                }
+                self.expect_peek(TokenKind::Rbrace, "`,` or `}` in a hash pattern")?;
+                Ok(Pattern::Hash(HashPattern {
+                    fields,
+                    span: token.span.to(self.current_span()),
+                }))
+            }
+            _ => bail!("expected a pattern, got `{}`", token.val),
+        }
+    }
+
+    // `pattern = default` for an element or key that may be missing.
+    fn parse_default(&mut self, pattern: Pattern) -> Result<Pattern> {
+        if self.peek_token_kind() != Some(TokenKind::Assign) {
+            return Ok(pattern);
+        }
+        self.next_token();
+        self.next_token();
+        let default = self.parse_expression(OperatorPrecedence::Lowest)?;
+        Ok(Pattern::Default(DefaultPattern {
+            span: pattern.span().to(default.span()),
+            pattern: Box::new(pattern),
+            default,
+        }))
     }
 
     fn parse_block_statement(&mut self) -> Result<BlockStatement> {
         let mut block_statement = BlockStatement {
             statements: Vec::new(),
+            span: self.current_span(),
         };
         self.next_token();
 
@@ -268,6 +588,7 @@ impl<'a> Parser<'a> {
             block_statement.statements.push(stmt);
             self.next_token();
         }
+        block_statement.span = block_statement.span.to(self.current_span());
         Ok(block_statement)
     }
 
@@ -276,7 +597,6 @@ impl<'a> Parser<'a> {
         self.next_token();
 
         if let Some(TokenKind::Rparen) = self.current_token_kind() {
-            self.next_token();
             return Ok(identifiers);
         }
 
@@ -288,6 +608,7 @@ impl<'a> Parser<'a> {
                     .expect("already validated")
                     .val
                     .to_string(),
+                span: self.current_span(),
             }),
             _ => bail!("identifier not found for parameters"),
         };
@@ -300,6 +621,7 @@ impl<'a> Parser<'a> {
                 match &self.current_token_kind() {
                     Some(TokenKind::Ident) => identifiers.push(Identifier {
                         value: self.current_token.clone().unwrap().val.to_string(),
+                        span: self.current_span(),
                     }),
                     _ => bail!("identifier not found for parameters"),
                 };
@@ -320,7 +642,6 @@ impl<'a> Parser<'a> {
         self.next_token();
 
         if let Some(TokenKind::Rparen) = self.current_token_kind() {
-            self.next_token();
             return Ok(expressions);
         }
         let expression = self.parse_expression(OperatorPrecedence::Lowest)?;
@@ -380,19 +701,24 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_expression_statement(&mut self) -> Result<Statement> {
+        let start = self.current_span();
         let expression = self.parse_expression(OperatorPrecedence::Lowest)?;
 
         if let Some(TokenKind::Semicolon) = self.peek_token_kind() {
             self.next_token();
         }
 
-        Ok(Statement::Expression(ExpressionStatement { expression }))
+        Ok(Statement::Expression(ExpressionStatement {
+            expression,
+            span: start.to(self.current_span()),
+        }))
     }
 
     fn parse_statement(&mut self) -> Result<Statement> {
         match self.current_token_kind() {
             Some(TokenKind::Let) => self.parse_let_statement(),
             Some(TokenKind::Return) => self.parse_return_statement(),
+            Some(TokenKind::Throw) => self.parse_throw_statement(),
             _ => self.parse_expression_statement(),
         }
     }
@@ -403,10 +729,13 @@ impl<'a> Parser<'a> {
         while self.current_token.is_some() {
             match self.parse_statement() {
                 Ok(stmt) => p.statments.push(stmt),
-                Err(err) => p.errors.push(err.to_string()),
+                Err(err) => p
+                    .errors
+                    .push(Diagnostic::error(err.to_string(), self.current_span())),
             }
             self.next_token();
         }
+        p.comments = self.lexer.take_comments();
         p
     }
 }
@@ -417,7 +746,7 @@ mod tests {
     use std::mem;
 
     use crate::{
-        ast::{CallableExpression, Expression, Identifier, IntegerLiteral, Operator, Statement},
+        ast::{CallableExpression, Expression, Operator, Statement},
         lexer::Lexer,
     };
 
@@ -439,14 +768,14 @@ mod tests {
     fn test_let_statement(statement: &Statement, val: &str) {
         if let Statement::Let(statement) = statement {
             assert_eq!(
-                statement.name.value.as_str(),
+                statement.name().unwrap().value.as_str(),
                 val,
                 "name value do not match: {}, {}",
-                statement.name.value.as_str(),
+                statement.name().unwrap().value.as_str(),
                 val
             );
         } else {
-            assert!(false, "statment was not let: {:?}", statement);
+            panic!("statment was not let: {:?}", statement);
         }
     }
 
@@ -477,16 +806,11 @@ mod tests {
         }
     }
 
-    pub fn test_infix_exp(
-        exp: &Expression,
-        left: &Expression,
-        operator: Operator,
-        right: &Expression,
-    ) {
+    pub fn test_infix_exp(exp: &Expression, left: &str, operator: Operator, right: &str) {
         match exp {
             Expression::Infix(exp) => {
-                assert_eq!(exp.left.as_ref(), left);
-                assert_eq!(exp.right.as_ref(), right);
+                assert_eq!(exp.left.to_string(), left);
+                assert_eq!(exp.right.to_string(), right);
                 assert_eq!(exp.operator, operator);
             }
             _ => panic!("expression is not identifier"),
@@ -541,21 +865,11 @@ let foobar = 838383;
             program.statments.len()
         );
 
-        let tests = vec![
-            Identifier {
-                value: "x".to_string(),
-            },
-            Identifier {
-                value: "y".to_string(),
-            },
-            Identifier {
-                value: "foobar".to_string(),
-            },
-        ];
+        let tests = ["x", "y", "foobar"];
 
         for (i, ident) in tests.iter().enumerate() {
             let stmt = program.statments.get(i).unwrap();
-            test_let_statement(stmt, &ident.value);
+            test_let_statement(stmt, ident);
         }
     }
 
@@ -574,7 +888,7 @@ let foobar = 838383;
             program.statments.len()
         );
 
-        let stmt = program.statments.get(0).unwrap();
+        let stmt = program.statments.first().unwrap();
 
         match stmt {
             Statement::Expression(exp) => {
@@ -599,7 +913,7 @@ let foobar = 838383;
             program.statments.len()
         );
 
-        let stmt = program.statments.get(0).unwrap();
+        let stmt = program.statments.first().unwrap();
 
         match stmt {
             Statement::Expression(exp) => {
@@ -624,7 +938,7 @@ let foobar = 838383;
             program.statments.len()
         );
 
-        let stmt = program.statments.get(0).unwrap();
+        let stmt = program.statments.first().unwrap();
 
         match stmt {
             Statement::Expression(exp) => {
@@ -658,7 +972,7 @@ let foobar = 838383;
             program.statments.len()
         );
 
-        let stmt = program.statments.get(0).unwrap();
+        let stmt = program.statments.first().unwrap();
 
         match stmt {
             Statement::Expression(exp) => {
@@ -696,7 +1010,7 @@ let foobar = 838383;
                 program.statments.len()
             );
 
-            let stmt = program.statments.get(0).unwrap();
+            let stmt = program.statments.first().unwrap();
 
             match stmt {
                 Statement::Expression(exp) => match &exp.expression {
@@ -780,7 +1094,7 @@ let foobar = 838383;
                 program.statments.len()
             );
 
-            let stmt = program.statments.get(0).unwrap();
+            let stmt = program.statments.first().unwrap();
 
             match stmt {
                 Statement::Expression(exp) => match &exp.expression {
@@ -814,22 +1128,13 @@ let foobar = 838383;
             program.statments.len()
         );
 
-        let stmt = program.statments.get(0).unwrap();
+        let stmt = program.statments.first().unwrap();
 
         match stmt {
             Statement::Expression(exp) => {
                 match &exp.expression {
                     Expression::If(if_exp) => {
-                        test_infix_exp(
-                            if_exp.condition.as_ref(),
-                            &Expression::Callable(CallableExpression::Identifier(Identifier {
-                                value: "x".to_string(),
-                            })),
-                            Operator::Lt,
-                            &Expression::Callable(CallableExpression::Identifier(Identifier {
-                                value: "y".to_string(),
-                            })),
-                        );
+                        test_infix_exp(if_exp.condition.as_ref(), "x", Operator::Lt, "y");
 
                         assert_eq!(
                             1,
@@ -842,7 +1147,7 @@ let foobar = 838383;
                         let stmt = if_exp
                             .consequence
                             .statements
-                            .get(0)
+                            .first()
                             .expect("invalid condition");
 
                         match stmt {
@@ -874,22 +1179,13 @@ let foobar = 838383;
             program.statments.len()
         );
 
-        let stmt = program.statments.get(0).unwrap();
+        let stmt = program.statments.first().unwrap();
 
         match stmt {
             Statement::Expression(exp) => {
                 match &exp.expression {
                     Expression::If(if_exp) => {
-                        test_infix_exp(
-                            if_exp.condition.as_ref(),
-                            &Expression::Callable(CallableExpression::Identifier(Identifier {
-                                value: "x".to_string(),
-                            })),
-                            Operator::Lt,
-                            &Expression::Callable(CallableExpression::Identifier(Identifier {
-                                value: "y".to_string(),
-                            })),
-                        );
+                        test_infix_exp(if_exp.condition.as_ref(), "x", Operator::Lt, "y");
 
                         assert_eq!(
                             1,
@@ -901,7 +1197,7 @@ let foobar = 838383;
                         let stmt = if_exp
                             .consequence
                             .statements
-                            .get(0)
+                            .first()
                             .expect("invalid condition");
 
                         match stmt {
@@ -919,7 +1215,7 @@ let foobar = 838383;
                             "invalid number of alternative statements: {}",
                             alternative.statements.len(),
                         );
-                        let stmt = alternative.statements.get(0).expect("invalid condition");
+                        let stmt = alternative.statements.first().expect("invalid condition");
 
                         match stmt {
                             Statement::Expression(exp) => {
@@ -950,7 +1246,7 @@ let foobar = 838383;
             program.statments.len()
         );
 
-        let stmt = program.statments.get(0).unwrap();
+        let stmt = program.statments.first().unwrap();
 
         match stmt {
             Statement::Expression(exp) => match &exp.expression {
@@ -961,7 +1257,7 @@ let foobar = 838383;
                         "invalid number of parameters: {}",
                         function.parameters.len(),
                     );
-                    let param = function.parameters.get(0).unwrap();
+                    let param = function.parameters.first().unwrap();
                     assert_eq!("x", param.value);
                     let param = function.parameters.get(1).unwrap();
                     assert_eq!("y", param.value);
@@ -973,19 +1269,12 @@ let foobar = 838383;
                         function.body.statements.len(),
                     );
 
-                    let stmt = function.body.statements.get(0).expect("invalid body");
+                    let stmt = function.body.statements.first().expect("invalid body");
 
                     match stmt {
-                        Statement::Expression(exp) => test_infix_exp(
-                            &exp.expression,
-                            &Expression::Callable(CallableExpression::Identifier(Identifier {
-                                value: "x".to_string(),
-                            })),
-                            Operator::Plus,
-                            &Expression::Callable(CallableExpression::Identifier(Identifier {
-                                value: "y".to_string(),
-                            })),
-                        ),
+                        Statement::Expression(exp) => {
+                            test_infix_exp(&exp.expression, "x", Operator::Plus, "y")
+                        }
                         _ => panic!("body statment is not identifier expression"),
                     }
                 }
@@ -1010,19 +1299,14 @@ let foobar = 838383;
             program.statments.len()
         );
 
-        let stmt = program.statments.get(0).unwrap();
+        let stmt = program.statments.first().unwrap();
 
         match stmt {
             Statement::Expression(exp) => match &exp.expression {
                 Expression::Call(call_expression) => {
                     match &call_expression.func {
                         CallableExpression::Identifier(ident) => {
-                            assert_eq!(
-                                ident,
-                                &Identifier {
-                                    value: "add".to_string()
-                                }
-                            )
+                            assert_eq!(ident.value, "add")
                         }
                         _ => panic!("func is not an identifier"),
                     };
@@ -1032,18 +1316,18 @@ let foobar = 838383;
                         "invalid number of arguments: {}",
                         call_expression.arguments.len(),
                     );
-                    test_int_literal(call_expression.arguments.get(0).unwrap(), 1);
+                    test_int_literal(call_expression.arguments.first().unwrap(), 1);
                     test_infix_exp(
                         call_expression.arguments.get(1).unwrap(),
-                        &Expression::IntegerLiteral(IntegerLiteral { value: 2 }),
+                        "2",
                         Operator::Asterisk,
-                        &Expression::IntegerLiteral(IntegerLiteral { value: 3 }),
+                        "3",
                     );
                     test_infix_exp(
                         call_expression.arguments.get(2).unwrap(),
-                        &Expression::IntegerLiteral(IntegerLiteral { value: 4 }),
+                        "4",
                         Operator::Plus,
-                        &Expression::IntegerLiteral(IntegerLiteral { value: 5 }),
+                        "5",
                     );
                 }
                 _ => panic!("Expression is not a function"),
@@ -1052,6 +1336,202 @@ let foobar = 838383;
         }
     }
 
+    #[test]
+    fn test_empty_parameter_and_argument_lists() {
+        let tests = vec![
+            ("fn() {}; 1", vec!["fn() { }", "1"]),
+            ("f(); 1", vec!["f()", "1"]),
+            (
+                "let g = fn() { f() }; g()",
+                vec!["let g = fn() { f() };", "g()"],
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = Parser::new(Lexer::new(input)).parse_program();
+            assert!(program.errors.is_empty(), "{}: {:?}", input, program.errors);
+            let statements: Vec<_> = program.statments.iter().map(|s| s.to_string()).collect();
+            assert_eq!(statements, expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_try_expression() {
+        let tests = vec![
+            (
+                "try { f() } catch (e) { e }",
+                Ok("try { f() } catch (e) { e }"),
+            ),
+            (
+                "try { f() } finally { g() }",
+                Ok("try { f() } finally { g() }"),
+            ),
+            ("throw 1 + 2;", Ok("throw 1 + 2;")),
+            (
+                "try { f() }",
+                Err("expected `catch` or `finally` after the `try` block, got end of input"),
+            ),
+            (
+                "try { f() } catch e { e }",
+                Err("expected `(` after `catch`, got `e`"),
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = Parser::new(Lexer::new(input)).parse_program();
+            match expected {
+                Ok(output) => {
+                    assert!(program.errors.is_empty(), "{:?}", program.errors);
+                    assert_eq!(program.statments[0].to_string(), output);
+                }
+                Err(message) => assert_eq!(program.errors[0].message, message, "{}", input),
+            }
+        }
+    }
+
+    #[test]
+    fn test_match_expression() {
+        let tests = vec![
+            (
+                "match (x) { 1 => \"one\", n if n > 1 => n, _ => 0, }",
+                Ok("match (x) { 1 => \"one\", n if n > 1 => n, _ => 0 }"),
+            ),
+            (
+                "match (p) { [a, [b], ...rest] => a, [...all] => all, {name, age: [y]} => y }",
+                Ok("match (p) { [a, [b], ...rest] => a, [...all] => all, {name, age: [y]} => y }"),
+            ),
+            ("match (x) {}", Ok("match (x) { }")),
+            (
+                "match (s) { {kind: \"circle\", r} => r, {w, h = 1} if w == h => w, }",
+                Ok("match (s) { {kind: \"circle\", r} => r, {w, h = 1} if w == h => w }"),
+            ),
+            (
+                "match (x) { 1 => { let y = 2; y * 3 }, _ => ({\"a\": 1}) }",
+                Ok("match (x) { 1 => { let y = 2; y * 3 }, _ => ({\"a\": 1}) }"),
+            ),
+            (
+                "match (x) { a + 1 => a }",
+                Err("expected `=>` after the pattern, got `+`"),
+            ),
+            (
+                "match (x) { f() => 1 }",
+                Err("expected `=>` after the pattern, got `(`"),
+            ),
+            (
+                "match (x) { 1 => 1 2 => 2 }",
+                Err("expected `,` or `}` after a match arm, got `2`"),
+            ),
+            (
+                "match (x) { [a, ...b, c] => 1 }",
+                Err("expected `,` or `]` in an array pattern, got `,`"),
+            ),
+            (
+                "match (x) { {1} => 1 }",
+                Err("expected a key in a hash pattern, got `1`"),
+            ),
+            ("match (x) { => 1 }", Err("expected a pattern, got `=>`")),
+            (
+                "match (x) { [a, a] => a }",
+                Err("`a` is bound more than once in the pattern"),
+            ),
+            (
+                "match (x) { [_, _] => 1, [a] => a, _ => 0 }",
+                Ok("match (x) { [_, _] => 1, [a] => a, _ => 0 }"),
+            ),
+            ("match x { }", Err("expected `(` after `match`, got `x`")),
+        ];
+
+        for (input, expected) in tests {
+            let program = Parser::new(Lexer::new(input)).parse_program();
+            match expected {
+                Ok(output) => {
+                    assert!(program.errors.is_empty(), "{:?}", program.errors);
+                    assert_eq!(program.statments[0].to_string(), output);
+                }
+                Err(message) => assert_eq!(program.errors[0].message, message, "{}", input),
+            }
+        }
+    }
+
+    #[test]
+    fn test_array_and_hash_literals() {
+        let tests = vec![
+            ("[1, 2 * 3, [x]]", Ok("[1, 2 * 3, [x]]")),
+            ("[]", Ok("[]")),
+            (
+                "{\"name\": \"ada\", 1 + 1: [true],}",
+                Ok("{\"name\": \"ada\", 1 + 1: [true]}"),
+            ),
+            ("{}", Ok("{}")),
+            ("[1 2]", Err("expected `,` or `]` in an array, got `2`")),
+            ("{\"a\" 1}", Err("expected `:` after a hash key, got `1`")),
+            (
+                "{\"a\": 1 \"b\": 2}",
+                Err("expected `,` or `}` in a hash, got `b`"),
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = Parser::new(Lexer::new(input)).parse_program();
+            match expected {
+                Ok(output) => {
+                    assert!(program.errors.is_empty(), "{:?}", program.errors);
+                    assert_eq!(program.statments[0].to_string(), output);
+                }
+                Err(message) => assert_eq!(program.errors[0].message, message, "{}", input),
+            }
+        }
+    }
+
+    #[test]
+    fn test_destructuring_let() {
+        let tests = vec![
+            (
+                "let [a, b = 1, ...rest] = xs",
+                Ok("let [a, b = 1, ...rest] = xs;"),
+            ),
+            (
+                "let {name, age: years = 0, address: {city}} = p;",
+                Ok("let {name, age: years = 0, address: {city}} = p;"),
+            ),
+            (
+                "let {nick = \"none\"} = p;",
+                Ok("let {nick = \"none\"} = p;"),
+            ),
+            ("let [] = xs;", Ok("let [] = xs;")),
+            ("let 1 = x;", Err("expected `let <pattern> =`, got `1`")),
+            (
+                "let [a b] = xs;",
+                Err("expected `,` or `]` in an array pattern, got `b`"),
+            ),
+            ("let {a: } = xs;", Err("expected a pattern, got `}`")),
+            ("let [a] xs;", Err("expected `let <pattern> =`, got `xs`")),
+            (
+                "let [a, a] = [1, 2];",
+                Err("`a` is bound more than once in the pattern"),
+            ),
+            (
+                "let {name, info: {name}} = p;",
+                Err("`name` is bound more than once in the pattern"),
+            ),
+            (
+                "let [a, ...a] = xs;",
+                Err("`a` is bound more than once in the pattern"),
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = Parser::new(Lexer::new(input)).parse_program();
+            match expected {
+                Ok(output) => {
+                    assert!(program.errors.is_empty(), "{:?}", program.errors);
+                    assert_eq!(program.statments[0].to_string(), output);
+                }
+                Err(message) => assert_eq!(program.errors[0].message, message, "{}", input),
+            }
+        }
+    }
+
     #[test]
     fn test_playground() {
         let input = "let x = 2; return x + 2;";
@@ -1,43 +1,751 @@
-use anyhow::{Context, Result};
-use std::io::{BufRead, Read, Write};
+use anyhow::{bail, Context, Result};
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
+};
 
-use crate::{environment::Environment, evaluator::Evaluator, lexer::Lexer, parser::Parser};
+use crate::{
+    debugger::{Breakpoints, DebugFrontend, Debugger, Pause, PauseReason, Resume},
+    evaluator::{CancellationToken, EvalBudget},
+    interpreter::{Interpreter, ParseError},
+    lexer::{is_incomplete, Lexer},
+    line_editor::{LineEditor, ReadLine},
+    object::Object,
+    parser::Parser,
+    shared_io::{SharedReader, SharedWriter},
+    token::TokenKind,
+};
 
+const HELP: &str = "\
+:env            list the global bindings
+:ast <src>      print the syntax tree of <src>
+:tokens <src>   print the tokens of <src>
+:type <expr>    print the type of the value of <expr>
+:load <file>    evaluate a file in this session
+:save <file>    write the inputs evaluated so far as a script
+:restore <file> evaluate a saved script in a fresh session
+:debug <file>   evaluate a file step by step, `help` lists the debugger commands
+:reset          forget every global binding
+:help           print this message";
+
+const DEBUG_HELP: &str = "\
+step, s               run to the next statement, entering calls
+next, n               run to the next statement of this function
+out, o                run until this function returns
+continue, c           run to the next breakpoint
+break, b [line|name]  pause at a line or when a function is called, list them without
+delete, d <line|name> remove a breakpoint
+env                   print the bindings of every scope, innermost first
+print, p <name>       print the value of a binding
+where, bt             print the calls in progress
+quit, q               abandon the evaluation";
+
+// Scripts read and print through the same streams as the REPL itself.
 pub struct Repl<R, W> {
-    reader: R,
-    writer: W,
+    reader: SharedReader<R>,
+    writer: SharedWriter<W>,
+    interpreter: Interpreter,
+    cancellation: CancellationToken,
+    editor: Option<LineEditor>,
+    // inputs that evaluated successfully, replayed by `:restore` after a `:save`
+    session: Vec<String>,
+    prelude: Option<String>,
 }
 
 impl<R, W> Repl<R, W>
 where
-    R: Read + BufRead,
-    W: Write,
+    R: Read + BufRead + Send + 'static,
+    W: Write + Send + 'static,
 {
     pub fn new(reader: R, writer: W) -> Self {
-        Repl { reader, writer }
+        let reader = SharedReader::new(reader);
+        let writer = SharedWriter::new(writer);
+        let cancellation = CancellationToken::new();
+        let interpreter = Interpreter::new()
+            .with_cancellation_token(cancellation.clone())
+            .with_output(writer.clone())
+            // unbuffered so `gets` leaves the following lines for the REPL
+            .with_input(BufReader::with_capacity(1, reader.clone()));
+        Repl {
+            reader,
+            writer,
+            interpreter,
+            cancellation,
+            editor: None,
+            session: Vec::new(),
+            prelude: None,
+        }
     }
-    pub fn start(mut self) -> Result<()> {
-        let mut repl_env = Environment::default();
+
+    /// Evaluates the file at `path` before the first input, and again whenever the
+    /// session is reset.
+    pub fn with_prelude(mut self, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let prelude = fs::read_to_string(path)
+            .with_context(|| format!("unable to read prelude {}", path.display()))?;
+        self.eval(&prelude)
+            .with_context(|| format!("failed to evaluate prelude {}", path.display()))?;
+        self.prelude = Some(prelude);
+        Ok(self)
+    }
+
+    /// Reads statements from a terminal line editor instead of the reader, which is then
+    /// only used by `gets`.
+    pub fn with_line_editor(mut self, editor: LineEditor) -> Self {
+        self.editor = Some(editor);
+        self
+    }
+
+    pub fn with_max_call_depth(mut self, max_call_depth: usize) -> Self {
+        self.interpreter = self.interpreter.with_max_call_depth(max_call_depth);
+        self
+    }
+
+    /// Limits applied to each line evaluated by the REPL.
+    pub fn with_budget(mut self, budget: EvalBudget) -> Self {
+        self.interpreter = self.interpreter.with_budget(budget);
+        self
+    }
+
+    /// Token that interrupts the line currently being evaluated, without ending the
+    /// session. It is cleared before and after every evaluation.
+    pub fn with_cancellation_token(mut self, cancellation: CancellationToken) -> Self {
+        self.interpreter = self
+            .interpreter
+            .with_cancellation_token(cancellation.clone());
+        self.cancellation = cancellation;
+        self
+    }
+
+    fn prompt(&mut self, prompt: &str) -> Result<()> {
+        write!(self.writer, "{}", prompt).context("unable to write to stdout")?;
+        self.writer.flush().context("unable to flush writer")
+    }
+
+    /// Reads lines until they form a complete statement. Two empty continuation lines in
+    /// a row give up and evaluate what was typed so far, a single one may be part of a
+    /// pasted function. `None` once the input is exhausted.
+    fn read_input(&mut self) -> Result<Option<String>> {
+        if let Some(editor) = &mut self.editor {
+            let names = self.interpreter.globals().into_iter().map(|(name, _)| name);
+            editor.set_identifiers(names.collect());
+        }
+
+        let mut buffer = String::new();
+        let mut prompt = ">> ";
         loop {
-            write!(self.writer, ">> ").context("unable to write to stdout")?;
-            self.writer.flush().context("unable to flush writer")?;
-            let mut buffer = String::new();
-            self.reader
-                .read_line(&mut buffer)
-                .context("failed to read line")?;
-
-            let lexer = Lexer::new(&buffer);
-            let parser = Parser::new(lexer);
-
-            let program = parser.parse_program();
-            if !program.errors.is_empty() {
-                for err in program.errors {
+            let read = self.read_line(prompt, &mut buffer)?;
+            if read == 0 && buffer.trim().is_empty() {
+                // leave the terminal on a fresh line after Ctrl-D
+                writeln!(self.writer).context("unable to write to stdout")?;
+                return Ok(None);
+            }
+            let blank_lines = buffer
+                .lines()
+                .rev()
+                .take_while(|line| line.trim().is_empty())
+                .count();
+            let give_up = buffer.trim().is_empty() || blank_lines >= 2;
+            if read == 0 || give_up || !is_incomplete(&buffer) {
+                return Ok(Some(buffer));
+            }
+            prompt = ".. ";
+        }
+    }
+
+    /// Appends the next line to `buffer`, returning how much was read, 0 at EOF.
+    fn read_line(&mut self, prompt: &str, buffer: &mut String) -> Result<usize> {
+        let Some(editor) = &mut self.editor else {
+            self.prompt(prompt)?;
+            return self
+                .reader
+                .lock()
+                .read_line(buffer)
+                .context("failed to read line");
+        };
+        match editor.read_line(prompt)? {
+            ReadLine::Line(line) => {
+                buffer.push_str(&line);
+                buffer.push('\n');
+                Ok(line.len() + 1)
+            }
+            ReadLine::Interrupted => {
+                // a blank line ends the input, which is then skipped
+                buffer.clear();
+                buffer.push('\n');
+                Ok(1)
+            }
+            ReadLine::Eof => Ok(0),
+        }
+    }
+
+    fn eval(&mut self, source: &str) -> Result<Object> {
+        self.cancellation.reset();
+        let result = self.interpreter.eval_str(source);
+        self.cancellation.reset();
+        result
+    }
+
+    fn debug(&mut self, source: &str) -> Result<Object> {
+        // the debugger prompts through the line editor while it runs
+        let editor = Arc::new(Mutex::new(self.editor.take()));
+        let frontend = ReplDebugger {
+            reader: self.reader.clone(),
+            writer: self.writer.clone(),
+            editor: editor.clone(),
+            source: source.to_string(),
+        };
+        self.cancellation.reset();
+        let result = self
+            .interpreter
+            .debug_str(source, Debugger::new(frontend).with_stop_on_entry());
+        self.cancellation.reset();
+        self.editor = editor.lock().unwrap_or_else(PoisonError::into_inner).take();
+        result
+    }
+
+    fn eval_recorded(&mut self, source: &str) -> Result<Object> {
+        let obj = self.eval(source)?;
+        self.session.push(source.trim().to_string());
+        Ok(obj)
+    }
+
+    /// Back to a fresh environment with only the prelude evaluated.
+    fn reset(&mut self) -> Result<()> {
+        self.interpreter.reset();
+        self.session.clear();
+        if let Some(prelude) = self.prelude.clone() {
+            self.eval(&prelude).context("failed to evaluate prelude")?;
+        }
+        Ok(())
+    }
+
+    /// The recorded inputs as one program, each ending with a semicolon so that
+    /// consecutive expressions are not parsed as a call.
+    fn session_script(&self) -> String {
+        let mut script = String::new();
+        for input in &self.session {
+            script.push_str(input);
+            let mut lexer = Lexer::new(input);
+            let last = lexer.by_ref().last().map(|token| token.kind);
+            if last.is_some_and(|kind| kind != TokenKind::Semicolon) {
+                // a trailing comment would swallow it
+                let comments = lexer.take_comments();
+                if comments
+                    .last()
+                    .is_some_and(|comment| comment.span.end == input.len())
+                {
+                    script.push('\n');
+                }
+                script.push(';');
+            }
+            script.push('\n');
+        }
+        script
+    }
+
+    fn print_error(&mut self, err: &anyhow::Error) -> Result<()> {
+        match err.downcast_ref::<ParseError>() {
+            Some(parse_error) => {
+                for err in &parse_error.errors {
                     writeln!(self.writer, "Error {}", err).context("unable to write to stdout")?;
                 }
+            }
+            None => writeln!(self.writer, "Error {}", err).context("unable to write to stdout")?,
+        }
+        Ok(())
+    }
+
+    /// Runs a `:` command, `input` being the line without the colon.
+    fn run_command(&mut self, input: &str) -> Result<()> {
+        let (command, arg) = match input.split_once(char::is_whitespace) {
+            Some((command, arg)) => (command, arg.trim()),
+            None => (input, ""),
+        };
+        let requires_arg = matches!(
+            command,
+            "ast" | "tokens" | "type" | "load" | "save" | "restore" | "debug"
+        );
+        if requires_arg && arg.is_empty() {
+            bail!(":{} expects an argument, see :help", command);
+        }
+
+        match command {
+            "env" => {
+                for (name, value) in self.interpreter.globals() {
+                    writeln!(self.writer, "{} = {}", name, value.inspect())
+                        .context("unable to write to stdout")?;
+                }
+            }
+            "ast" => {
+                let program = Parser::new(Lexer::new(arg)).parse_program();
+                if !program.errors.is_empty() {
+                    bail!(ParseError {
+                        errors: program.errors
+                    });
+                }
+                for statement in &program.statments {
+                    writeln!(self.writer, "{:#?}", statement)
+                        .context("unable to write to stdout")?;
+                }
+            }
+            "tokens" => {
+                for token in Lexer::new(arg) {
+                    writeln!(self.writer, "{:?} {:?}", token.kind, token.val)
+                        .context("unable to write to stdout")?;
+                }
+            }
+            "type" => {
+                let obj = self.eval(arg)?;
+                writeln!(self.writer, "{}", obj.type_val()).context("unable to write to stdout")?;
+            }
+            "load" => {
+                let source =
+                    fs::read_to_string(arg).with_context(|| format!("unable to read {}", arg))?;
+                let obj = self.eval_recorded(&source)?;
+                writeln!(self.writer, "{}", obj.inspect()).context("unable to write to stdout")?;
+            }
+            "save" => {
+                fs::write(arg, self.session_script())
+                    .with_context(|| format!("unable to write {}", arg))?;
+            }
+            "restore" => {
+                let source =
+                    fs::read_to_string(arg).with_context(|| format!("unable to read {}", arg))?;
+                self.reset()?;
+                let obj = self.eval_recorded(&source)?;
+                writeln!(self.writer, "{}", obj.inspect()).context("unable to write to stdout")?;
+            }
+            "debug" => {
+                let source =
+                    fs::read_to_string(arg).with_context(|| format!("unable to read {}", arg))?;
+                let obj = self.debug(&source)?;
+                self.session.push(source.trim().to_string());
+                writeln!(self.writer, "{}", obj.inspect()).context("unable to write to stdout")?;
+            }
+            "reset" => self.reset()?,
+            "help" => writeln!(self.writer, "{}", HELP).context("unable to write to stdout")?,
+            _ => bail!("unknown command :{}, see :help", command),
+        }
+        Ok(())
+    }
+
+    /// Runs until the input reaches EOF. Lines starting with `:` are REPL commands, see
+    /// `:help`.
+    pub fn start(mut self) -> Result<()> {
+        while let Some(buffer) = self.read_input()? {
+            let input = buffer.trim();
+            if input.is_empty() {
+                continue;
+            }
+            if let Some(editor) = &mut self.editor {
+                if let Err(err) = editor.add_history(buffer.trim_end()) {
+                    self.print_error(&err)?;
+                }
+            }
+
+            let result = match input.strip_prefix(':') {
+                Some(command) => self.run_command(command),
+                None => self.eval_recorded(&buffer).and_then(|obj| {
+                    writeln!(self.writer, "{}", obj.inspect()).context("unable to write to stdout")
+                }),
+            };
+            if let Err(err) = result {
+                self.print_error(&err)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Prompts for debugger commands at each pause of `:debug`.
+struct ReplDebugger<R, W> {
+    reader: SharedReader<R>,
+    writer: SharedWriter<W>,
+    editor: Arc<Mutex<Option<LineEditor>>>,
+    source: String,
+}
+
+impl<R, W> ReplDebugger<R, W>
+where
+    R: BufRead + Send,
+    W: Write + Send,
+{
+    /// The next command, `None` once the input is exhausted.
+    fn read_command(&mut self) -> Result<Option<String>> {
+        if let Some(editor) = self
+            .editor
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_mut()
+        {
+            return match editor.read_line("(debug) ")? {
+                ReadLine::Line(line) => Ok(Some(line)),
+                ReadLine::Interrupted => Ok(Some(String::new())),
+                ReadLine::Eof => Ok(None),
+            };
+        }
+
+        write!(self.writer, "(debug) ").context("unable to write to stdout")?;
+        self.writer.flush().context("unable to flush writer")?;
+        let mut line = String::new();
+        let read = self
+            .reader
+            .lock()
+            .read_line(&mut line)
+            .context("failed to read line")?;
+        Ok((read > 0).then_some(line))
+    }
+
+    fn print_scopes(&mut self, pause: &Pause) -> Result<()> {
+        let scopes = pause.scopes();
+        for (idx, scope) in scopes.iter().enumerate() {
+            let name = if idx + 1 == scopes.len() {
+                "globals"
+            } else if idx == 0 {
+                "locals"
             } else {
-                let obj = Evaluator::eval(program, &mut repl_env)?;
-                writeln!(self.writer, "{}", obj).context("unable to write to stdout")?;
+                "closure"
+            };
+            let bindings: Vec<_> = scope
+                .iter()
+                .map(|(name, value)| format!("{} = {}", name, value.inspect()))
+                .collect();
+            writeln!(self.writer, "{}: {}", name, bindings.join(", "))
+                .context("unable to write to stdout")?;
+        }
+        Ok(())
+    }
+
+    fn print_frames(&mut self, pause: &Pause) -> Result<()> {
+        for frame in pause.frames.iter().rev() {
+            writeln!(
+                self.writer,
+                "in {}, called at line {}",
+                frame.function, frame.line
+            )
+            .context("unable to write to stdout")?;
+        }
+        writeln!(self.writer, "in <main>").context("unable to write to stdout")
+    }
+}
+
+impl<R, W> DebugFrontend for ReplDebugger<R, W>
+where
+    R: BufRead + Send,
+    W: Write + Send,
+{
+    fn paused(&mut self, pause: &Pause, breakpoints: &mut Breakpoints) -> Result<Resume> {
+        let reason = match pause.reason {
+            PauseReason::Entry => "entry",
+            PauseReason::Breakpoint => "breakpoint",
+            PauseReason::FunctionBreakpoint => "function breakpoint",
+            PauseReason::Step => "step",
+        };
+        let text = self.source.lines().nth(pause.line - 1).unwrap_or("");
+        writeln!(
+            self.writer,
+            "stopped at line {} ({}): {}",
+            pause.line,
+            reason,
+            text.trim()
+        )
+        .context("unable to write to stdout")?;
+
+        loop {
+            let Some(line) = self.read_command()? else {
+                return Ok(Resume::Stop);
+            };
+            let (command, arg) = match line.trim().split_once(char::is_whitespace) {
+                Some((command, arg)) => (command, arg.trim()),
+                None => (line.trim(), ""),
+            };
+            let message = match command {
+                "" => continue,
+                "step" | "s" => return Ok(Resume::StepIn),
+                "next" | "n" => return Ok(Resume::StepOver),
+                "out" | "o" => return Ok(Resume::StepOut),
+                "continue" | "c" => return Ok(Resume::Continue),
+                "quit" | "q" => return Ok(Resume::Stop),
+                "break" | "b" if arg.is_empty() => {
+                    let lines = breakpoints
+                        .lines
+                        .iter()
+                        .map(|line| format!("line {}", line));
+                    let functions = breakpoints.functions.iter().cloned();
+                    lines.chain(functions).collect::<Vec<_>>().join("\n")
+                }
+                "break" | "b" => {
+                    match arg.parse() {
+                        Ok(line) => breakpoints.lines.insert(line),
+                        Err(_) => breakpoints.functions.insert(arg.to_string()),
+                    };
+                    continue;
+                }
+                "delete" | "d" => {
+                    let removed = match arg.parse() {
+                        Ok(line) => breakpoints.lines.remove(&line),
+                        Err(_) => breakpoints.functions.remove(arg),
+                    };
+                    if removed {
+                        continue;
+                    }
+                    format!("no breakpoint at {}", arg)
+                }
+                "env" => {
+                    self.print_scopes(pause)?;
+                    continue;
+                }
+                "print" | "p" => match pause.lookup(arg) {
+                    Some(value) => value.inspect(),
+                    None => format!("identifier not found: {}", arg),
+                },
+                "where" | "bt" => {
+                    self.print_frames(pause)?;
+                    continue;
+                }
+                "help" => DEBUG_HELP.to_string(),
+                _ => format!("unknown debugger command {}, see help", command),
+            };
+            if !message.is_empty() {
+                writeln!(self.writer, "{}", message).context("unable to write to stdout")?;
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::Cursor,
+        path::{Path, PathBuf},
+    };
+
+    use crate::shared_io::SharedWriter;
+
+    use super::Repl;
+
+    fn run(input: &str) -> String {
+        run_with_prelude(input, None)
+    }
+
+    fn run_with_prelude(input: &str, prelude: Option<&Path>) -> String {
+        let output = SharedWriter::new(Vec::new());
+        let mut repl = Repl::new(Cursor::new(input.to_string()), output.clone());
+        if let Some(prelude) = prelude {
+            repl = repl.with_prelude(prelude).unwrap();
+        }
+        repl.start().unwrap();
+        let output = String::from_utf8(output.lock().clone()).unwrap();
+        output
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("repl-{}-{}.monkey", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_session() {
+        let tests = vec![
+            ("", ">> \n"),
+            ("1 + 2\n", ">> 3\n>> \n"),
+            ("\n\n", ">> >> >> \n"),
+            // the last line may lack a newline
+            ("let x = 5;\nx * 2", ">> null\n>> 10\n>> \n"),
+            (
+                "let add = fn(x, y) {\n x + y\n};\nadd(1, 2)\n",
+                ">> .. .. null\n>> 3\n>> \n",
+            ),
+            (
+                "puts(\"hi\")\nfoo\n",
+                ">> hi\nnull\n>> Error identifier not found: foo\n>> \n",
+            ),
+            (
+                "let twice = fn(f, x) { f(f(x)) };\n\"a\" + \"b\"\n",
+                ">> null\n>> \"ab\"\n>> \n",
+            ),
+            (
+                "fn(x) {\n  x * (x + 1)\n}\n",
+                ">> .. .. fn(x) { x * (x + 1) }\n>> \n",
+            ),
+            ("// just a comment\n", ">> null\n>> \n"),
+            // EOF in the middle of a statement still evaluates it
+            ("1 +", ">> .. Error cannot parse expression\n>> \n"),
+            // an empty line inside a pasted function does not end it, two of them do
+            (
+                "let f = fn(x) {\n let y = x;\n\n y\n};\nf(2)\n",
+                ">> .. .. .. .. null\n>> 2\n>> \n",
+            ),
+            (
+                "1 +\n\n\n2\n",
+                ">> .. .. Error cannot parse expression\n>> 2\n>> \n",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(run(input), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_commands() {
+        let script = temp_file("load");
+        fs::write(&script, "let loaded = 40;\nloaded + 2").unwrap();
+        let comments = temp_file("comments");
+        fs::write(&comments, "// only a comment\n").unwrap();
+
+        let tests = vec![
+            (
+                ":env\nlet b = true;\nlet a = 1;\n:env\n".to_string(),
+                ">> >> null\n>> null\n>> a = 1\nb = true\n>> \n",
+            ),
+            (
+                ":tokens let x = \"hi\";\n".to_string(),
+                ">> Let \"let\"\nIdent \"x\"\nAssign \"=\"\nString \"hi\"\nSemicolon \";\"\n>> \n",
+            ),
+            (
+                ":ast x\n".to_string(),
+                ">> Expression(\n    ExpressionStatement {\n        expression: Callable(\n            Identifier(\n                Identifier {\n                    value: \"x\",\n                    span: Span {\n                        start: 0,\n                        end: 1,\n                    },\n                },\n            ),\n        ),\n        span: Span {\n            start: 0,\n            end: 1,\n        },\n    },\n)\n>> \n",
+            ),
+            (
+                ":ast let = 1;\n".to_string(),
+                ">> Error expected `let <pattern> =`, got `=`\nError unexpected `=` at the start of an expression\n>> \n",
+            ),
+            (
+                ":type 1 + 1\n:type fn(x) { x }\n:type puts\n".to_string(),
+                ">> INTEGER\n>> FUNCTION\n>> BUILTIN\n>> \n",
+            ),
+            (
+                format!(":load {}\nloaded\n", script.display()),
+                ">> 42\n>> 40\n>> \n",
+            ),
+            (
+                format!(":load {}\n", comments.display()),
+                ">> null\n>> \n",
+            ),
+            (
+                "let x = 1;\n:reset\nx\n".to_string(),
+                ">> null\n>> >> Error identifier not found: x\n>> \n",
+            ),
+            (
+                ":type\n:nope\n".to_string(),
+                ">> Error :type expects an argument, see :help\n>> Error unknown command :nope, see :help\n>> \n",
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(run(&input), expected, "{:?}", input);
+        }
+        fs::remove_file(script).unwrap();
+        fs::remove_file(comments).unwrap();
+
+        assert!(run(":help\n").contains(":load <file>"));
+    }
+
+    #[test]
+    fn test_save_and_restore() {
+        let saved = temp_file("save");
+        let input = format!(
+            "let a = 2;\nmissing\nlet double = fn(x) {{\n x * a\n}};\ndouble(3)\n:type a\n:save {}\n",
+            saved.display()
+        );
+        assert_eq!(
+            run(&input),
+            ">> null\n>> Error identifier not found: missing\n>> .. .. null\n>> 6\n>> INTEGER\n>> >> \n"
+        );
+        assert_eq!(
+            fs::read_to_string(&saved).unwrap(),
+            "let a = 2;\nlet double = fn(x) {\n x * a\n};\ndouble(3);\n"
+        );
+
+        let input = format!(
+            "let leftover = 1;\n:restore {}\ndouble(a)\nleftover\n",
+            saved.display()
+        );
+        assert_eq!(
+            run(&input),
+            ">> null\n>> 6\n>> 4\n>> Error identifier not found: leftover\n>> \n"
+        );
+
+        let input = format!(
+            "let x = 5 // five\n-1\nlet y = 1; // one\n:save {}\n",
+            saved.display()
+        );
+        run(&input);
+        assert_eq!(
+            fs::read_to_string(&saved).unwrap(),
+            "let x = 5 // five\n;\n-1;\nlet y = 1; // one\n"
+        );
+        let input = format!(":restore {}\nx\n", saved.display());
+        assert_eq!(run(&input), ">> null\n>> 5\n>> \n");
+
+        run(&format!("// nothing\n1\n:save {}\n", saved.display()));
+        assert_eq!(fs::read_to_string(&saved).unwrap(), "// nothing\n1;\n");
+        fs::remove_file(saved).unwrap();
+    }
+
+    #[test]
+    fn test_prelude() {
+        let prelude = temp_file("prelude");
+        fs::write(&prelude, "let greeting = \"hello\";").unwrap();
+
+        let input = "greeting\nlet greeting = 1;\n:reset\ngreeting\n";
+        assert_eq!(
+            run_with_prelude(input, Some(&prelude)),
+            ">> \"hello\"\n>> null\n>> >> \"hello\"\n>> \n"
+        );
+
+        fs::write(&prelude, "let = 1;").unwrap();
+        let output = SharedWriter::new(Vec::new());
+        let err = Repl::new(Cursor::new(String::new()), output)
+            .with_prelude(&prelude)
+            .err()
+            .unwrap();
+        assert!(err.to_string().starts_with("failed to evaluate prelude"));
+        fs::remove_file(prelude).unwrap();
+    }
+
+    #[test]
+    fn test_debug() {
+        let script = temp_file("debug");
+        fs::write(
+            &script,
+            "let add = fn(a, b) {\n  let sum = a + b;\n  sum\n};\nlet x = add(1, 2);\nx + 1",
+        )
+        .unwrap();
+
+        let input = format!(
+            ":debug {}\nb 3\nb\nc\nenv\np sum\nwhere\nd 7\nn\n\nn\nx\n",
+            script.display()
+        );
+        assert_eq!(
+            run(&input),
+            ">> stopped at line 1 (entry): let add = fn(a, b) {\n\
+             (debug) (debug) line 3\n\
+             (debug) stopped at line 3 (breakpoint): sum\n\
+             (debug) locals: a = 1, b = 2, sum = 3\n\
+             globals: add = fn(a, b) { let sum = a + b; sum }\n\
+             (debug) 3\n\
+             (debug) in add, called at line 5\n\
+             in <main>\n\
+             (debug) no breakpoint at 7\n\
+             (debug) stopped at line 6 (step): x + 1\n\
+             (debug) (debug) 4\n\
+             >> 3\n\
+             >> \n"
+        );
+
+        // quitting abandons the evaluation
+        let input = format!(":debug {}\nq\nadd\n", script.display());
+        assert_eq!(
+            run(&input),
+            ">> stopped at line 1 (entry): let add = fn(a, b) {\n\
+             (debug) Error evaluation interrupted\n\
+             >> Error identifier not found: add\n\
+             >> \n"
+        );
+        fs::remove_file(script).unwrap();
+    }
+}
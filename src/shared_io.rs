@@ -0,0 +1,84 @@
+use std::{
+    io::{Read, Write},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+/// Cloneable handle to a writer, for hosts that hand script output to the interpreter
+/// and still need to read what was written.
+///
+/// ```
+/// use thorsten_interpreter::{interpreter::Interpreter, shared_io::SharedWriter};
+///
+/// let output = SharedWriter::new(Vec::new());
+/// let mut interpreter = Interpreter::new().with_output(output.clone());
+/// interpreter.eval_str("puts(\"hello\", 1 + 1)")?;
+///
+/// assert_eq!(output.lock().as_slice(), b"hello\n2\n");
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct SharedWriter<W> {
+    inner: Arc<Mutex<W>>,
+}
+
+impl<W> SharedWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(writer)),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, W> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<W> Clone for SharedWriter<W> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<W: Write> Write for SharedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.lock().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.lock().flush()
+    }
+}
+
+/// Cloneable handle to a reader, see [`SharedWriter`].
+#[derive(Debug, Default)]
+pub struct SharedReader<R> {
+    inner: Arc<Mutex<R>>,
+}
+
+impl<R> SharedReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(reader)),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, R> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<R> Clone for SharedReader<R> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<R: Read> Read for SharedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.lock().read(buf)
+    }
+}
@@ -2,6 +2,32 @@
 pub struct Token<'a> {
     pub kind: TokenKind,
     pub val: &'a str,
+    pub span: Span,
+}
+
+/// Byte range of a piece of source.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// From the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end,
+        }
+    }
+}
+
+/// A `//` comment, which the parser skips but the formatter keeps.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Comment {
+    /// The text after the slashes, without the line break.
+    pub text: String,
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -17,6 +43,11 @@ pub enum TokenKind {
     If,
     Else,
     Return,
+    Throw,
+    Try,
+    Catch,
+    Finally,
+    Match,
 
     Illegal,
     Eof,
@@ -28,12 +59,20 @@ pub enum TokenKind {
     Rparen,
     Lbrace,
     Rbrace,
+    Lbracket,
+    Rbracket,
+    Colon,
+    // `=>` between a pattern and the value of a match arm
+    Arrow,
+    // `...` before the rest of an array pattern
+    Ellipsis,
 
     //Operators
     Assign,
     Plus,
     Minus,
     Bang,
+    Question,
     Asterisk,
     Slash,
 
@@ -42,3 +81,26 @@ pub enum TokenKind {
     Eq,
     NotEq,
 }
+
+/// Words the lexer reads as keywords instead of identifiers.
+pub const KEYWORDS: [(&str, TokenKind); 12] = [
+    ("let", TokenKind::Let),
+    ("fn", TokenKind::Function),
+    ("if", TokenKind::If),
+    ("else", TokenKind::Else),
+    ("true", TokenKind::True),
+    ("false", TokenKind::False),
+    ("return", TokenKind::Return),
+    ("throw", TokenKind::Throw),
+    ("try", TokenKind::Try),
+    ("catch", TokenKind::Catch),
+    ("finally", TokenKind::Finally),
+    ("match", TokenKind::Match),
+];
+
+pub fn lookup_keyword(ident: &str) -> Option<TokenKind> {
+    KEYWORDS
+        .iter()
+        .find(|(keyword, _)| *keyword == ident)
+        .map(|(_, kind)| kind.clone())
+}
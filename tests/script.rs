@@ -0,0 +1,63 @@
+use std::{
+    fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+};
+
+fn temp_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("script-{}-{}.monkey", name, std::process::id()))
+}
+
+// Pipes `program` into the interpreter, so it runs as a script rather than a session.
+fn run_script(program: &str, prelude: &str, name: &str) -> Output {
+    let path = temp_file(name);
+    fs::write(&path, prelude).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_thorsten-interpreter"))
+        .arg("--prelude")
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(program.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_file(path).unwrap();
+    output
+}
+
+#[test]
+fn test_prelude_keeps_line_numbers() {
+    let output = run_script(
+        "let y = 1;\nputs(f(y));\n",
+        "let f = fn(x) {\n  x + true\n};\n",
+        "lines",
+    );
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Error type mismatch: INTEGER + BOOLEAN\n  in f, called at line 2\n"
+    );
+}
+
+#[test]
+fn test_prelude_errors() {
+    let output = run_script("puts(1);\n", "let = 1;\n", "errors");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with("Error failed to evaluate prelude "),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("Error expected `let <pattern> =`, got `=`\n"));
+}